@@ -0,0 +1,116 @@
+use crate::contract_utils::keccak256;
+use ethereum_types::U256;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// State mutability of a function, as declared in the ABI
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateMutability {
+    Pure,
+    View,
+    NonPayable,
+    Payable,
+}
+
+/// A public function described by the ABI of a contract
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodSignature {
+    pub name: String,
+    /// Canonical types of the arguments, e.g. `uint256` or `(address,uint256)[]`
+    pub inputs: Vec<String>,
+    pub state_mutability: StateMutability,
+}
+
+/// Result of matching an ABI against the methods found by the analysis
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AbiReport {
+    /// Functions of the ABI that were not found by the analysis
+    pub missing: Vec<MethodSignature>,
+    /// Selectors found by the analysis that are not in the ABI, in ascending order
+    pub unknown: Vec<U256>,
+}
+
+impl MethodSignature {
+    /// The canonical signature used to compute the selector, e.g. `transfer(address,uint256)`
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.inputs.join(","))
+    }
+    /// The first 4 bytes of the keccak256 hash of the signature
+    pub fn selector(&self) -> U256 {
+        U256::from(&keccak256(self.signature().as_bytes())[..4])
+    }
+}
+
+impl fmt::Display for MethodSignature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{:?}]", self.signature(), self.state_mutability)
+    }
+}
+
+#[derive(Deserialize)]
+struct AbiEntry {
+    #[serde(rename = "type", default = "function_type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    inputs: Vec<AbiParam>,
+    #[serde(rename = "stateMutability")]
+    state_mutability: Option<StateMutability>,
+    // Used by solc < 0.5 instead of `stateMutability`
+    #[serde(default)]
+    constant: bool,
+    #[serde(default)]
+    payable: bool,
+}
+
+#[derive(Deserialize)]
+struct AbiParam {
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    components: Vec<AbiParam>,
+}
+
+fn function_type() -> String {
+    String::from("function")
+}
+
+impl AbiParam {
+    /// Tuples are written as the list of their components, followed by the array suffixes
+    fn canonical_type(&self) -> String {
+        match self.kind.strip_prefix("tuple") {
+            Some(suffix) => {
+                let components: Vec<String> =
+                    self.components.iter().map(|c| c.canonical_type()).collect();
+                format!("({}){}", components.join(","), suffix)
+            }
+            None => self.kind.clone(),
+        }
+    }
+}
+
+/// Reads the functions declared in a solc or Vyper ABI JSON
+///
+/// Constructors, events, errors, fallback and receive entries are skipped.
+pub fn parse_abi(json: &str) -> Result<Vec<MethodSignature>, serde_json::Error> {
+    let entries: Vec<AbiEntry> = serde_json::from_str(json)?;
+    Ok(entries
+        .into_iter()
+        .filter(|entry| entry.kind == "function")
+        .map(|entry| {
+            let state_mutability = match entry.state_mutability {
+                Some(mutability) => mutability,
+                None if entry.constant => StateMutability::View,
+                None if entry.payable => StateMutability::Payable,
+                None => StateMutability::NonPayable,
+            };
+            MethodSignature {
+                name: entry.name,
+                inputs: entry.inputs.iter().map(|i| i.canonical_type()).collect(),
+                state_mutability,
+            }
+        })
+        .collect())
+}
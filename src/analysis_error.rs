@@ -0,0 +1,45 @@
+use crate::evm_types::StackValue;
+use std::fmt;
+
+/// Reasons why the static analysis of a contract could not be completed
+///
+/// Every variant carries the position (`pc`) of the instruction that caused the failure.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnalysisError {
+    /// The target of a `JUMP`/`JUMPI` could not be resolved to a constant
+    UnresolvableJump { pc: usize, target: StackValue },
+    /// The target of a `JUMP`/`JUMPI` was resolved, but it is not the start of an analyzed function
+    InvalidJumpDestination { pc: usize, destination: usize },
+    /// A `PUSH` instruction whose data goes past the end of the code
+    TruncatedPush { pc: usize },
+    /// The constructor does not return a section of its own code.
+    ///
+    /// `pc` is the position of the `RETURN` instruction, or `0` if the constructor never returns.
+    MissingRuntimeCode { pc: usize },
+    /// A `SHA3` instruction whose memory offset or length are not constant
+    SymbolicSha3 { pc: usize },
+}
+
+impl fmt::Display for AnalysisError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AnalysisError::UnresolvableJump { pc, target } => {
+                write!(f, "unresolvable jump target at pc {}: {:?}", pc, target)
+            }
+            AnalysisError::InvalidJumpDestination { pc, destination } => write!(
+                f,
+                "invalid jump destination {:#x} at pc {}",
+                destination, pc
+            ),
+            AnalysisError::TruncatedPush { pc } => write!(f, "truncated PUSH data at pc {}", pc),
+            AnalysisError::MissingRuntimeCode { pc } => {
+                write!(f, "constructor does not return runtime code (pc {})", pc)
+            }
+            AnalysisError::SymbolicSha3 { pc } => {
+                write!(f, "SHA3 over a symbolic memory range at pc {}", pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnalysisError {}
@@ -0,0 +1,259 @@
+use serde::{Deserialize, Serialize};
+
+/// Metadata appended by the compiler to the bytecode, encoded as CBOR
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metadata {
+    /// `solc` or `vyper`
+    pub compiler: String,
+    /// Version of the compiler, e.g. `0.8.19`; solc < 0.5.9 does not include it
+    pub version: Option<String>,
+    /// Hash of the source metadata file as (kind, hash), the kind being `ipfs`, `bzzr0` or `bzzr1`
+    pub source_hash: Option<(String, Vec<u8>)>,
+    /// The contract was compiled with experimental features
+    pub experimental: bool,
+}
+
+/// Opcodes that end the execution of a block
+const TERMINATORS: [u8; 6] = [0x00, 0x56, 0xf3, 0xfd, 0xfe, 0xff];
+const JUMPDEST: u8 = 0x5b;
+const INVALID: u8 = 0xfe;
+
+/// Splits the bytecode into the part containing instructions and the compiler metadata
+///
+/// The metadata is removed from the end of the code, then the code is truncated after the last
+/// reachable instruction if it is followed by an `INVALID` opcode, which solc places in front of
+/// data sections (e.g. the runtime code in the deployment bytecode).
+pub fn split_code(code: &[u8]) -> (&[u8], Option<Metadata>) {
+    let (code, metadata) = match strip_metadata(code) {
+        Some((length, metadata)) => (&code[..length], Some(metadata)),
+        None => (code, None),
+    };
+    let end = instructions_end(code);
+    if end < code.len() && code[end] == INVALID {
+        (&code[..end], metadata)
+    } else {
+        (code, metadata)
+    }
+}
+
+/// Parses the compiler metadata at the end of the bytecode, if present
+pub fn parse_metadata(code: &[u8]) -> Option<Metadata> {
+    strip_metadata(code).map(|(_, metadata)| metadata)
+}
+
+/// Returns the length of the code without the metadata, together with the parsed metadata
+///
+/// The metadata is followed by its length as a 2 bytes big endian integer, which for Vyper >= 0.3.10
+/// includes the 2 bytes of the length itself.
+fn strip_metadata(code: &[u8]) -> Option<(usize, Metadata)> {
+    if code.len() < 2 {
+        return None;
+    }
+    let end = code.len() - 2;
+    let length = ((code[end] as usize) << 8) | code[end + 1] as usize;
+    for start in [end.checked_sub(length), code.len().checked_sub(length)].iter() {
+        let start = match start {
+            Some(start) if *start < end => *start,
+            _ => continue,
+        };
+        let mut decoder = Decoder {
+            data: &code[start..end],
+            position: 0,
+        };
+        if let Some(item) = decoder.item(0) {
+            if decoder.position == end - start {
+                if let Some(metadata) = read_metadata(&item) {
+                    return Some((start, metadata));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn read_metadata(item: &Cbor) -> Option<Metadata> {
+    let entries = match item {
+        Cbor::Map(entries) => entries,
+        // Vyper >= 0.3.10 wraps the map in an array with the sizes of the code sections
+        Cbor::Array(items) => return read_metadata(items.last()?),
+        _ => return None,
+    };
+    let mut metadata = Metadata {
+        compiler: String::from("solc"),
+        version: None,
+        source_hash: None,
+        experimental: false,
+    };
+    let mut known = false;
+    for (key, value) in entries {
+        let key = match key {
+            Cbor::Text(key) => key.as_str(),
+            _ => return None,
+        };
+        match (key, value) {
+            ("ipfs", Cbor::Bytes(hash))
+            | ("bzzr0", Cbor::Bytes(hash))
+            | ("bzzr1", Cbor::Bytes(hash)) => {
+                metadata.source_hash = Some((String::from(key), hash.clone()))
+            }
+            ("solc", Cbor::Bytes(version)) if version.len() == 3 => {
+                metadata.version = Some(format!("{}.{}.{}", version[0], version[1], version[2]))
+            }
+            // Prerelease versions are stored as a string
+            ("solc", Cbor::Text(version)) => metadata.version = Some(version.clone()),
+            ("vyper", Cbor::Array(version)) => {
+                let numbers: Option<Vec<String>> = version
+                    .iter()
+                    .map(|n| match n {
+                        Cbor::Int(n) => Some(n.to_string()),
+                        _ => None,
+                    })
+                    .collect();
+                metadata.compiler = String::from("vyper");
+                metadata.version = Some(numbers?.join("."));
+            }
+            ("experimental", Cbor::Bool(experimental)) => metadata.experimental = *experimental,
+            _ => continue,
+        }
+        known = true;
+    }
+    if known {
+        Some(metadata)
+    } else {
+        None
+    }
+}
+
+/// Returns the position following the last instruction reachable from the start of the code
+///
+/// Only jumps to targets pushed as constants are followed.
+fn instructions_end(code: &[u8]) -> usize {
+    // Positions of the instructions, to tell JUMPDESTs apart from PUSH data
+    let mut is_instruction = vec![false; code.len()];
+    let mut pc = 0;
+    while pc < code.len() {
+        is_instruction[pc] = true;
+        pc += push_size(code[pc]) + 1;
+    }
+    let mut visited = vec![false; code.len()];
+    let mut to_visit = vec![0];
+    let mut end = 0;
+    while let Some(mut pc) = to_visit.pop() {
+        while pc < code.len() && !visited[pc] {
+            visited[pc] = true;
+            let opcode = code[pc];
+            let size = push_size(opcode);
+            let next = usize::min(pc + size + 1, code.len());
+            if size > 0 && size <= 8 {
+                let target = code[pc + 1..next]
+                    .iter()
+                    .fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+                if (target as usize) < code.len()
+                    && is_instruction[target as usize]
+                    && code[target as usize] == JUMPDEST
+                {
+                    to_visit.push(target as usize);
+                }
+            }
+            end = usize::max(end, next);
+            if TERMINATORS.contains(&opcode) {
+                break;
+            }
+            pc = next;
+        }
+    }
+    end
+}
+
+/// Number of bytes of data following the opcode, non-zero only for the `PUSH` instructions
+pub(crate) fn push_size(opcode: u8) -> usize {
+    if (0x60..=0x7f).contains(&opcode) {
+        (opcode - 0x5f) as usize
+    } else {
+        0
+    }
+}
+
+/// The subset of CBOR used by the compilers
+enum Cbor {
+    Int(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+    Array(Vec<Cbor>),
+    Map(Vec<(Cbor, Cbor)>),
+    Bool(bool),
+    Null,
+}
+
+struct Decoder<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Decoder<'a> {
+    fn take(&mut self, n: usize) -> Option<&'a [u8]> {
+        let end = self.position.checked_add(n)?;
+        let bytes = self.data.get(self.position..end)?;
+        self.position = end;
+        Some(bytes)
+    }
+
+    fn argument(&mut self, info: u8) -> Option<u64> {
+        let size = match info {
+            0..=23 => return Some(info as u64),
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => return None,
+        };
+        let bytes = self.take(size)?;
+        Some(bytes.iter().fold(0, |acc, byte| (acc << 8) | *byte as u64))
+    }
+
+    fn item(&mut self, depth: usize) -> Option<Cbor> {
+        // Metadata is never deeply nested, deeper items are not metadata
+        if depth > 4 {
+            return None;
+        }
+        let initial = *self.take(1)?.first()?;
+        let info = initial & 0x1f;
+        match initial >> 5 {
+            0 => Some(Cbor::Int(self.argument(info)?)),
+            2 => {
+                let length = self.argument(info)? as usize;
+                Some(Cbor::Bytes(self.take(length)?.to_vec()))
+            }
+            3 => {
+                let length = self.argument(info)? as usize;
+                let text = std::str::from_utf8(self.take(length)?).ok()?;
+                Some(Cbor::Text(String::from(text)))
+            }
+            4 => {
+                let length = self.argument(info)? as usize;
+                let mut items = Vec::new();
+                for _ in 0..length {
+                    items.push(self.item(depth + 1)?);
+                }
+                Some(Cbor::Array(items))
+            }
+            5 => {
+                let length = self.argument(info)? as usize;
+                let mut entries = Vec::new();
+                for _ in 0..length {
+                    let key = self.item(depth + 1)?;
+                    let value = self.item(depth + 1)?;
+                    entries.push((key, value));
+                }
+                Some(Cbor::Map(entries))
+            }
+            7 => match info {
+                20 => Some(Cbor::Bool(false)),
+                21 => Some(Cbor::Bool(true)),
+                22 => Some(Cbor::Null),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use crate::analysis_error::AnalysisError;
+use crate::contract_data::ContractData;
+use crate::contract_utils::keccak256;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Version of the analyzer producing the encoded data
+pub const ANALYZER_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Version of the encoding, to be increased whenever the analysis results or the encoded
+/// types change
+pub const FORMAT_VERSION: u32 = 4;
+
+/// Errors returned when encoding or decoding a `ContractData`
+#[derive(Debug)]
+pub enum SerializationError {
+    /// The data was produced by a different version of the analyzer
+    Version {
+        analyzer: String,
+        format: u32,
+    },
+    Binary(bincode::Error),
+    Json(serde_json::Error),
+    Io(io::Error),
+}
+
+impl fmt::Display for SerializationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SerializationError::Version { analyzer, format } => write!(
+                f,
+                "data produced by analyzer {} (format {}), expected {} (format {})",
+                analyzer, format, ANALYZER_VERSION, FORMAT_VERSION
+            ),
+            SerializationError::Binary(e) => write!(f, "binary encoding: {}", e),
+            SerializationError::Json(e) => write!(f, "json encoding: {}", e),
+            SerializationError::Io(e) => write!(f, "io: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SerializationError {}
+
+impl From<bincode::Error> for SerializationError {
+    fn from(e: bincode::Error) -> Self {
+        SerializationError::Binary(e)
+    }
+}
+
+impl From<serde_json::Error> for SerializationError {
+    fn from(e: serde_json::Error) -> Self {
+        SerializationError::Json(e)
+    }
+}
+
+impl From<io::Error> for SerializationError {
+    fn from(e: io::Error) -> Self {
+        SerializationError::Io(e)
+    }
+}
+
+/// Versions written in front of the encoded data
+#[derive(Serialize, Deserialize)]
+struct Header {
+    analyzer: String,
+    format: u32,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a> {
+    analyzer: &'a str,
+    format: u32,
+    data: &'a ContractData,
+}
+
+#[derive(Deserialize)]
+struct OwnedEnvelope {
+    #[allow(dead_code)]
+    analyzer: String,
+    #[allow(dead_code)]
+    format: u32,
+    data: ContractData,
+}
+
+impl Header {
+    fn check(self) -> Result<(), SerializationError> {
+        if self.analyzer == ANALYZER_VERSION && self.format == FORMAT_VERSION {
+            Ok(())
+        } else {
+            Err(SerializationError::Version {
+                analyzer: self.analyzer,
+                format: self.format,
+            })
+        }
+    }
+}
+
+fn envelope(data: &ContractData) -> Envelope<'_> {
+    Envelope {
+        analyzer: ANALYZER_VERSION,
+        format: FORMAT_VERSION,
+        data,
+    }
+}
+
+impl ContractData {
+    ///
+    /// Encodes the contract data in a compact binary format, tagged with the analyzer version
+    ///
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SerializationError> {
+        Ok(bincode::serialize(&envelope(self))?)
+    }
+    ///
+    /// Decodes data encoded with `to_bytes` by the same version of the analyzer
+    ///
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SerializationError> {
+        // The header is decoded first, as the layout of the data depends on the version
+        bincode::deserialize::<Header>(bytes)?.check()?;
+        Ok(bincode::deserialize::<OwnedEnvelope>(bytes)?.data)
+    }
+    ///
+    /// Encodes the contract data as JSON, tagged with the analyzer version
+    ///
+    pub fn to_json(&self) -> Result<String, SerializationError> {
+        Ok(serde_json::to_string(&envelope(self))?)
+    }
+    ///
+    /// Decodes data encoded with `to_json` by the same version of the analyzer
+    ///
+    pub fn from_json(json: &str) -> Result<Self, SerializationError> {
+        serde_json::from_str::<Header>(json)?.check()?;
+        Ok(serde_json::from_str::<OwnedEnvelope>(json)?.data)
+    }
+}
+
+/// Results of previous analyses stored in a local directory, keyed by the keccak256 hash of the
+/// runtime code
+///
+/// Entries hold the analysis of the runtime code, with an empty constructor: deployments of the
+/// same contract with different constructor arguments share the entry (see
+/// `analyze_contract_cached`). Entries written by a different version of the analyzer are ignored
+/// and replaced.
+pub struct AnalysisCache {
+    directory: PathBuf,
+}
+
+/// Number of temporary files created by this process, to give each writer its own file
+static TEMPORARY_FILES: AtomicUsize = AtomicUsize::new(0);
+
+impl AnalysisCache {
+    /// Opens the cache stored in `directory`, creating it if needed
+    pub fn new<P: Into<PathBuf>>(directory: P) -> io::Result<Self> {
+        let directory = directory.into();
+        fs::create_dir_all(&directory)?;
+        Ok(AnalysisCache { directory })
+    }
+
+    fn path(&self, code: &[u8]) -> PathBuf {
+        let name: String = keccak256(code)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        self.directory.join(name + ".bin")
+    }
+
+    /// Returns the cached analysis of the runtime code `code`, if present and up to date
+    pub fn get(&self, code: &[u8]) -> Option<ContractData> {
+        let bytes = fs::read(self.path(code)).ok()?;
+        ContractData::from_bytes(&bytes).ok()
+    }
+
+    /// Stores the analysis of the runtime code `code`
+    pub fn insert(&self, code: &[u8], data: &ContractData) -> Result<(), SerializationError> {
+        let path = self.path(code);
+        // Write to a temporary file first, so that readers never see a partial entry; writers
+        // of the same entry in other threads or processes use different files
+        let count = TEMPORARY_FILES.fetch_add(1, Ordering::Relaxed);
+        let temporary = path.with_extension(format!("{}-{}.tmp", process::id(), count));
+        let result =
+            fs::write(&temporary, data.to_bytes()?).and_then(|_| fs::rename(&temporary, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        Ok(result?)
+    }
+
+    /// Returns the cached analysis of the runtime code `code`, running `analyze` and caching its
+    /// result on a miss
+    ///
+    /// Failing to write the cache entry is not an error, the analysis is returned anyway.
+    pub fn get_or_analyze<F>(&self, code: &[u8], analyze: F) -> Result<ContractData, AnalysisError>
+    where
+        F: FnOnce(&[u8]) -> Result<ContractData, AnalysisError>,
+    {
+        if let Some(data) = self.get(code) {
+            return Ok(data);
+        }
+        let data = analyze(code)?;
+        let _ = self.insert(code, &data);
+        Ok(data)
+    }
+}
@@ -0,0 +1,387 @@
+use crate::bytecode::split_code;
+use crate::contract_analyzer::list_functions;
+use crate::evm_function::{EvmFunction, FunctionRegistry};
+use crate::evm_memory::{EvmMemory, EvmStack};
+use crate::evm_types::StackValue::{self, ActualValue};
+use ethereum_types::U256;
+use std::collections::{HashMap, HashSet};
+
+/// The calls reaching each block: the calling block, with the stack and memory passed to the block
+type Callers<'a> = HashMap<usize, Vec<(usize, &'a EvmStack, &'a EvmMemory)>>;
+
+/// A block of a `FunctionRegistry`, executed from start to end
+///
+/// Blocks are the `EvmFunction`s of the analysis: they start at the beginning of the code, at a
+/// `JUMPDEST` or after a `JUMPI`, and end with a jump, an instruction halting the execution or
+/// before the next `JUMPDEST`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// Position of the first instruction
+    pub start: usize,
+    /// Position following the last instruction
+    pub end: usize,
+}
+
+/// Control flow graph of a bytecode, whose nodes are the blocks of a `FunctionRegistry`
+///
+/// Blocks are identified by their starting position. Jump targets that depend on the stack of the
+/// callers (e.g. the return address of an internal function) are resolved with the values passed
+/// by every call reaching the block, going back through the callers as long as needed.
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    pub blocks: HashMap<usize, BasicBlock>,
+    successors: HashMap<usize, HashSet<usize>>,
+    predecessors: HashMap<usize, HashSet<usize>>,
+    /// Edges whose target was only resolved with the stack of the callers
+    dynamic_edges: HashSet<(usize, usize)>,
+    /// Positions of the jumps whose target could not be resolved
+    pub unresolved_jumps: HashSet<usize>,
+    /// Immediate dominator of each block reachable from the start of the code
+    dominators: HashMap<usize, usize>,
+    loop_headers: HashSet<usize>,
+}
+
+impl Cfg {
+    /// Builds the graph of the blocks of `registry`
+    ///
+    /// Blocks reached from the start of the code are analyzed if needed, without logging them; the
+    /// blocks that were not analyzed are not part of the graph.
+    pub fn new(registry: &FunctionRegistry) -> Self {
+        let mut cfg = Cfg {
+            blocks: HashMap::new(),
+            successors: HashMap::new(),
+            predecessors: HashMap::new(),
+            dynamic_edges: HashSet::new(),
+            unresolved_jumps: HashSet::new(),
+            dominators: HashMap::new(),
+            loop_headers: HashSet::new(),
+        };
+        if registry.get_from_address(0).is_some() {
+            cfg.explore(registry);
+            cfg.compute_dominators();
+        }
+        for function in registry.analyzed() {
+            cfg.blocks.insert(
+                function.position,
+                BasicBlock {
+                    start: function.position,
+                    end: block_end(function),
+                },
+            );
+        }
+        cfg
+    }
+
+    /// Builds the graph of a bytecode, analyzing all its blocks on the current thread
+    ///
+    /// Unlike the function analyzers, unreachable blocks are analyzed too, so that they are part of
+    /// the graph.
+    pub fn from_code(code: &[u8]) -> Self {
+        let (instructions, _) = split_code(code);
+        let functions = list_functions(instructions);
+        let registry = FunctionRegistry::new(code, &functions);
+        for f_loc in &functions {
+            registry.get_from_address(*f_loc);
+        }
+        Cfg::new(&registry)
+    }
+
+    /// Adds the edges of the blocks reachable from the start of the code
+    ///
+    /// Blocks are taken from a worklist and every call is followed once. A block is processed again
+    /// when it gains a new caller, or when one of the blocks its jumps were resolved through does,
+    /// since the new call might pass other targets.
+    fn explore<'a>(&mut self, registry: &'a FunctionRegistry) {
+        let mut callers: Callers<'a> = HashMap::new();
+        // Blocks whose jumps were resolved through the callers of each block
+        let mut dependents: HashMap<usize, HashSet<usize>> = HashMap::new();
+        let mut followed: HashSet<(usize, usize, usize)> = HashSet::new();
+        let mut jumps = HashSet::new();
+        let mut resolved_jumps = HashSet::new();
+        let mut worklist = vec![0];
+        let mut queued: HashSet<usize> = worklist.iter().cloned().collect();
+        while let Some(position) = worklist.pop() {
+            queued.remove(&position);
+            let node = match registry.get_from_address(position) {
+                Some(node) => node,
+                None => continue,
+            };
+            for (index, call) in node.internal_calls.iter().enumerate() {
+                jumps.insert(call.4);
+                let dynamic = call.0.resolve().is_none();
+                let targets = if dynamic {
+                    let mut through = HashSet::new();
+                    let targets = resolve_from_callers(
+                        &callers,
+                        position,
+                        &call.0,
+                        &mut vec![position],
+                        &mut HashSet::new(),
+                        &mut through,
+                    );
+                    for block in through {
+                        dependents.entry(block).or_default().insert(position);
+                    }
+                    targets
+                } else {
+                    call.0.resolve().into_iter().collect()
+                };
+                for target in targets {
+                    let target = match target {
+                        target if target <= U256::from(usize::MAX) => target.as_usize(),
+                        _ => {
+                            self.unresolved_jumps.insert(call.4);
+                            continue;
+                        }
+                    };
+                    if registry.get_from_address(target).is_none() {
+                        self.unresolved_jumps.insert(call.4);
+                        continue;
+                    }
+                    resolved_jumps.insert(call.4);
+                    self.add_edge(position, target);
+                    if dynamic {
+                        self.dynamic_edges.insert((position, target));
+                    }
+                    if !followed.insert((position, index, target)) {
+                        continue;
+                    }
+                    callers
+                        .entry(target)
+                        .or_default()
+                        .push((position, &call.1, &call.2));
+                    let affected = dependents.get(&target).into_iter().flatten();
+                    for block in std::iter::once(&target).chain(affected) {
+                        if queued.insert(*block) {
+                            worklist.push(*block);
+                        }
+                    }
+                }
+            }
+        }
+        // Jumps without any valid target are unresolved too
+        self.unresolved_jumps
+            .extend(jumps.difference(&resolved_jumps).cloned());
+    }
+
+    fn add_edge(&mut self, from: usize, to: usize) {
+        self.successors.entry(from).or_default().insert(to);
+        self.predecessors.entry(to).or_default().insert(from);
+    }
+
+    /// Computes the immediate dominators with the algorithm by Cooper, Harvey and Kennedy, then
+    /// marks as loop headers the targets of the edges going back to one of their dominators
+    fn compute_dominators(&mut self) {
+        let order = self.postorder();
+        let index: HashMap<usize, usize> = order.iter().enumerate().map(|(i, b)| (*b, i)).collect();
+        self.dominators.insert(0, 0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for block in order.iter().rev().filter(|b| **b != 0) {
+                let mut new_dominator: Option<usize> = None;
+                for predecessor in self.predecessors(*block) {
+                    if !self.dominators.contains_key(&predecessor) {
+                        continue;
+                    }
+                    new_dominator = Some(match new_dominator {
+                        None => predecessor,
+                        Some(current) => self.intersect(predecessor, current, &index),
+                    });
+                }
+                if let Some(dominator) = new_dominator {
+                    if self.dominators.get(block) != Some(&dominator) {
+                        self.dominators.insert(*block, dominator);
+                        changed = true;
+                    }
+                }
+            }
+        }
+        for (from, targets) in &self.successors {
+            for to in targets {
+                if self.dominates(*to, *from) {
+                    self.loop_headers.insert(*to);
+                }
+            }
+        }
+    }
+
+    fn intersect(&self, mut a: usize, mut b: usize, index: &HashMap<usize, usize>) -> usize {
+        while a != b {
+            while index[&a] < index[&b] {
+                a = self.dominators[&a];
+            }
+            while index[&b] < index[&a] {
+                b = self.dominators[&b];
+            }
+        }
+        a
+    }
+
+    /// Blocks reachable from the start of the code, in postorder
+    fn postorder(&self) -> Vec<usize> {
+        let mut order = Vec::new();
+        let mut visited = HashSet::new();
+        visited.insert(0);
+        let mut stack = vec![(0, self.successors(0))];
+        while let Some((block, mut successors)) = stack.pop() {
+            match successors.pop() {
+                Some(next) => {
+                    stack.push((block, successors));
+                    if visited.insert(next) {
+                        stack.push((next, self.successors(next)));
+                    }
+                }
+                None => order.push(block),
+            }
+        }
+        order
+    }
+
+    /// Blocks that can be executed after `block`, in ascending order
+    pub fn successors(&self, block: usize) -> Vec<usize> {
+        sorted(self.successors.get(&block))
+    }
+
+    /// Blocks that can be executed before `block`, in ascending order
+    pub fn predecessors(&self, block: usize) -> Vec<usize> {
+        sorted(self.predecessors.get(&block))
+    }
+
+    /// Whether the jump from `from` to `to` was only resolved with the stack of the callers
+    pub fn is_dynamic_edge(&self, from: usize, to: usize) -> bool {
+        self.dynamic_edges.contains(&(from, to))
+    }
+
+    /// The last block, other than itself, executed by every path reaching `block`
+    pub fn immediate_dominator(&self, block: usize) -> Option<usize> {
+        match self.dominators.get(&block) {
+            Some(dominator) if *dominator != block => Some(*dominator),
+            _ => None,
+        }
+    }
+
+    /// Whether every path from the start of the code to `block` goes through `dominator`
+    pub fn dominates(&self, dominator: usize, mut block: usize) -> bool {
+        if !self.dominators.contains_key(&block) {
+            return false;
+        }
+        loop {
+            if block == dominator {
+                return true;
+            }
+            match self.immediate_dominator(block) {
+                Some(next) => block = next,
+                None => return false,
+            }
+        }
+    }
+
+    /// Whether `block` is the entry of a loop
+    pub fn is_loop_header(&self, block: usize) -> bool {
+        self.loop_headers.contains(&block)
+    }
+
+    /// Entries of the loops of the code, in ascending order
+    pub fn loop_headers(&self) -> Vec<usize> {
+        sorted(Some(&self.loop_headers))
+    }
+
+    /// Whether `block` can be reached from the start of the code
+    pub fn is_reachable(&self, block: usize) -> bool {
+        self.dominators.contains_key(&block)
+    }
+
+    /// Blocks reachable from the start of the code, in ascending order
+    pub fn reachable_blocks(&self) -> Vec<usize> {
+        let mut blocks: Vec<usize> = self.dominators.keys().cloned().collect();
+        blocks.sort();
+        blocks
+    }
+
+    /// Writes the graph in the Graphviz DOT format
+    ///
+    /// Loop headers are drawn with a double border, dynamic edges are dashed and unreachable blocks
+    /// are grey.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        let mut blocks: Vec<&BasicBlock> = self.blocks.values().collect();
+        blocks.sort_by_key(|block| block.start);
+        for block in blocks {
+            let mut attributes = format!("label=\"{:#x}..{:#x}\"", block.start, block.end);
+            if self.is_loop_header(block.start) {
+                attributes += ", peripheries=2";
+            }
+            if !self.is_reachable(block.start) {
+                attributes += ", color=grey, fontcolor=grey";
+            }
+            dot += &format!("    b{} [{}];\n", block.start, attributes);
+        }
+        let mut sources: Vec<&usize> = self.successors.keys().collect();
+        sources.sort();
+        for from in sources {
+            for to in self.successors(*from) {
+                if self.is_dynamic_edge(*from, to) {
+                    dot += &format!("    b{} -> b{} [style=dashed];\n", from, to);
+                } else {
+                    dot += &format!("    b{} -> b{};\n", from, to);
+                }
+            }
+        }
+        dot += "}\n";
+        dot
+    }
+}
+
+/// The values `value` can take in `block`, given the stack and memory passed by its callers
+///
+/// Values still depending on the callers are resolved with the callers of the caller, without
+/// entering a block twice in the same chain of calls. The blocks whose callers were used are
+/// added to `through`.
+fn resolve_from_callers(
+    callers: &Callers,
+    block: usize,
+    value: &StackValue,
+    chain: &mut Vec<usize>,
+    visited: &mut HashSet<(usize, StackValue)>,
+    through: &mut HashSet<usize>,
+) -> HashSet<U256> {
+    through.insert(block);
+    let mut targets = HashSet::new();
+    for (caller, stack, memory) in callers.get(&block).into_iter().flatten() {
+        let value = value.replace_parent_call(stack, memory);
+        if let Some(target) = value.resolve() {
+            targets.insert(target);
+            continue;
+        }
+        let from_caller = value.contains(&|v| {
+            matches!(
+                v,
+                StackValue::StackPaceHolder(_) | StackValue::MemoryPlaceHolder(_, _)
+            )
+        });
+        if from_caller && !chain.contains(caller) && visited.insert((*caller, value.clone())) {
+            chain.push(*caller);
+            targets.extend(resolve_from_callers(
+                callers, *caller, &value, chain, visited, through,
+            ));
+            chain.pop();
+        }
+    }
+    targets
+}
+
+/// Position following the last instruction of the block
+fn block_end(function: &EvmFunction) -> usize {
+    match function.internal_calls.last() {
+        // Falling through into a JUMPDEST: the block ends before it
+        Some((ActualValue(target), _, _, None, pc)) if *target == U256::from(*pc) => *pc,
+        _ => usize::min(function.pc, function.code.len()),
+    }
+}
+
+fn sorted(blocks: Option<&HashSet<usize>>) -> Vec<usize> {
+    let mut blocks: Vec<usize> = blocks.map_or(Vec::new(), |b| b.iter().cloned().collect());
+    blocks.sort();
+    blocks
+}
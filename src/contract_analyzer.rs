@@ -1,47 +1,156 @@
+use crate::analysis_error::AnalysisError;
+use crate::bytecode::{push_size, split_code};
+use crate::cache::AnalysisCache;
 use crate::contract_data::{ContractData, ContractMethod};
-use crate::contract_utils::get_pubblic_method;
+use crate::contract_utils::get_calldata_size_guard;
 //use crate::evm_execution::EvmExecution;
 use crate::contract_logger::{ContractLogger, NoLogger};
 use crate::cycle_resolution::CycleSolver;
 use crate::cycle_resolution::NocycleSolver;
+use crate::dispatcher::{DefaultDispatcher, DispatcherRecognizer};
 use crate::evm_function::{EvmFunction, FunctionRegistry};
 use crate::evm_memory::{EvmMemory, EvmStack};
 use crate::evm_types::{StackValue, StackValue::*};
 use crate::function_analyzer::{
-    multi_threded_function_analyzer, single_threded_function_analyzer, FunctionAnalyzer,
+    single_threded_function_analyzer, AnalyzerConfig, BlockAnalyzer, FunctionAnalyzer,
 };
 use ethereum_types::U256;
-use std::collections::HashMap;
 
-pub fn analyze_contract_default(code: &[u8]) -> Option<ContractData> {
+pub fn analyze_contract_default(code: &[u8]) -> Result<ContractData, AnalysisError> {
     analyze_contract(
         code,
         &NocycleSolver(),
-        &(multi_threded_function_analyzer as FunctionAnalyzer<NoLogger>),
+        &DefaultDispatcher(),
+        &AnalyzerConfig::default(),
         &mut NoLogger(),
     )
 }
-pub fn analyze_contract_single(code: &[u8]) -> Option<ContractData> {
+pub fn analyze_contract_single(code: &[u8]) -> Result<ContractData, AnalysisError> {
     analyze_contract(
         code,
         &NocycleSolver(),
+        &DefaultDispatcher(),
         &(single_threded_function_analyzer as FunctionAnalyzer<NoLogger>),
         &mut NoLogger(),
     )
 }
 
-pub fn analyze_contract<L>(
+pub fn analyze_runtime_code_default(code: &[u8]) -> Result<ContractData, AnalysisError> {
+    analyze_runtime_code(
+        code,
+        &NocycleSolver(),
+        &DefaultDispatcher(),
+        &AnalyzerConfig::default(),
+        &mut NoLogger(),
+    )
+}
+
+/// Analyzes the deployment bytecode of a contract: the constructor and the runtime code it returns
+///
+/// The result is passed to `logger.log_contract_data`, then the logger is finalized.
+pub fn analyze_contract<L: ContractLogger>(
+    code: &[u8],
+    cycle_solver: &dyn CycleSolver,
+    recognizer: &dyn DispatcherRecognizer,
+    analyzer: &dyn BlockAnalyzer<L>,
+    logger: &mut L,
+) -> Result<ContractData, AnalysisError> {
+    let result = resolve_contract(code, cycle_solver, recognizer, analyzer, logger, None);
+    finalize_logger(&result, logger);
+    result
+}
+
+/// Analyzes the deployment bytecode like `analyze_contract`, reusing the analysis of the runtime
+/// code stored in `cache`
+///
+/// The constructor is always analyzed, as it depends on the constructor arguments appended to the
+/// bytecode; the analysis of the runtime code it returns is looked up by the hash of the runtime
+/// code, and stored in the cache on a miss.
+pub fn analyze_contract_cached<L: ContractLogger>(
+    code: &[u8],
+    cycle_solver: &dyn CycleSolver,
+    recognizer: &dyn DispatcherRecognizer,
+    analyzer: &dyn BlockAnalyzer<L>,
+    logger: &mut L,
+    cache: &AnalysisCache,
+) -> Result<ContractData, AnalysisError> {
+    let result = resolve_contract(
+        code,
+        cycle_solver,
+        recognizer,
+        analyzer,
+        logger,
+        Some(cache),
+    );
+    finalize_logger(&result, logger);
+    result
+}
+
+/// Analyzes the code of a deployed contract, as returned by `EXTCODECOPY` or `eth_getCode`
+///
+/// The `constructor` of the returned `ContractData` is empty. The result is passed to
+/// `logger.log_contract_data`, then the logger is finalized.
+pub fn analyze_runtime_code<L: ContractLogger>(
+    code: &[u8],
+    cycle_solver: &dyn CycleSolver,
+    recognizer: &dyn DispatcherRecognizer,
+    analyzer: &dyn BlockAnalyzer<L>,
+    logger: &mut L,
+) -> Result<ContractData, AnalysisError> {
+    let mut contract = ContractData::new();
+    let result = resolve_runtime_code(
+        code,
+        cycle_solver,
+        recognizer,
+        analyzer,
+        logger,
+        &mut contract,
+    )
+    .map(|_| contract);
+    finalize_logger(&result, logger);
+    result
+}
+
+/// Returns the runtime code deployed by the constructor in `code`
+///
+/// Constant values written by the constructor into the code are applied, while symbolic
+/// immutables are left as zeros.
+pub fn extract_runtime_code(code: &[u8]) -> Result<Vec<u8>, AnalysisError> {
+    let (instructions, _) = split_code(code);
+    let functions = list_functions(instructions);
+    let registry = FunctionRegistry::new(code, &functions);
+    let start = entry_point(&registry, &mut NoLogger())?;
+    match resolve_return_node(start, &registry, Vec::new(), &mut NoLogger())? {
+        Some((_, CodeSection(v), _, _)) => Ok(v),
+        Some((pc, _, _, _)) => Err(AnalysisError::MissingRuntimeCode { pc }),
+        None => Err(AnalysisError::MissingRuntimeCode { pc: 0 }),
+    }
+}
+
+fn finalize_logger(result: &Result<ContractData, AnalysisError>, logger: &mut impl ContractLogger) {
+    if let Ok(contract) = result {
+        logger.log_contract_data(contract.clone());
+    }
+    logger.finalize();
+}
+
+/// Analyzes the constructor of a contract and the runtime code it returns
+fn resolve_contract<L: ContractLogger>(
     code: &[u8],
     cycle_solver: &dyn CycleSolver,
-    analyzer: &FunctionAnalyzer<L>,
+    recognizer: &dyn DispatcherRecognizer,
+    analyzer: &dyn BlockAnalyzer<L>,
     logger: &mut L,
-) -> Option<ContractData> {
-    let functions = list_functions(code);
-    let registry = analyzer(code, &functions, logger);
+    cache: Option<&AnalysisCache>,
+) -> Result<ContractData, AnalysisError> {
+    // Metadata and data sections do not contain functions
+    let (instructions, metadata) = split_code(code);
+    let functions = list_functions(instructions);
+    let registry = analyzer.analyze(code, &functions, logger);
     // Get storage access
-    let start = &registry.analyzed[&0];
+    let start = entry_point(&registry, logger)?;
     let mut constructor = ContractMethod::new();
-    let mut storage = HashMap::new();
+    let mut contract = ContractData::new();
 
     resolve_function_storage(
         start,
@@ -50,44 +159,174 @@ pub fn analyze_contract<L>(
         &mut constructor,
         false,
         cycle_solver,
-        &mut storage,
+        recognizer,
+        &mut contract,
         vec![0],
-    );
+        logger,
+    )?;
 
     //get return value
-    let retv = resolve_return_node(start, &registry, Vec::new());
+    let retv = resolve_return_node(start, &registry, Vec::new(), logger)?;
     //println!("{:?}", retv);
-    if let Some(CodeSection(v)) = retv {
-        let code = &v[..];
-        let functions = list_functions(code);
-        let registry = analyzer(code, &functions, logger);
-        let start = &registry.analyzed[&0];
-        let mut temporary = ContractMethod::new();
-        resolve_function_storage(
-            start,
-            &registry,
-            Vec::new(),
-            &mut temporary,
-            false,
-            cycle_solver,
-            &mut storage,
-            vec![0],
-        );
-        return Some(ContractData::construct(constructor, storage));
+    match retv {
+        Some((_, CodeSection(v), immutables, complete)) => {
+            let mut analyze_runtime = |code: &[u8]| {
+                let mut runtime = ContractData::new();
+                runtime.immutables = immutables.clone();
+                resolve_runtime_code(
+                    code,
+                    cycle_solver,
+                    recognizer,
+                    analyzer,
+                    logger,
+                    &mut runtime,
+                )
+                .map(|_| runtime)
+            };
+            // The code of symbolic immutables is not enough to identify the analysis
+            let mut runtime = match cache {
+                Some(cache) if immutables.is_empty() => {
+                    cache.get_or_analyze(&v[..], analyze_runtime)?
+                }
+                _ => analyze_runtime(&v[..])?,
+            };
+            if !complete {
+                // The constructor might have modified any part of the runtime code
+                for method in runtime.methods.values_mut() {
+                    method.mark_incomplete();
+                }
+                for method in runtime.fallback.iter_mut().chain(&mut runtime.receive) {
+                    method.mark_incomplete();
+                }
+            }
+            contract.methods.extend(runtime.methods);
+            contract.fallback = runtime.fallback;
+            contract.receive = runtime.receive;
+            contract.metadata = runtime.metadata;
+            contract.set_constructor(constructor);
+            contract.immutables = immutables;
+            // Vyper only appends the metadata to the deployment bytecode
+            if contract.metadata.is_none() {
+                contract.metadata = metadata;
+            }
+            Ok(contract)
+        }
+        Some((pc, _, _, _)) => Err(AnalysisError::MissingRuntimeCode { pc }),
+        None => Err(AnalysisError::MissingRuntimeCode { pc: 0 }),
     }
-    None
 }
 
-pub fn resolve_function_storage(
+/// Adds the public methods, the fallback and the receive function of the runtime code to `contract`
+fn resolve_runtime_code<L: ContractLogger>(
+    code: &[u8],
+    cycle_solver: &dyn CycleSolver,
+    recognizer: &dyn DispatcherRecognizer,
+    analyzer: &dyn BlockAnalyzer<L>,
+    logger: &mut L,
+    contract: &mut ContractData,
+) -> Result<(), AnalysisError> {
+    let (instructions, metadata) = split_code(code);
+    let functions = list_functions(instructions);
+    let registry = analyzer.analyze(code, &functions, logger);
+    let start = entry_point(&registry, logger)?;
+    // Whatever is not dispatched to a public method is executed by the fallback
+    let mut fallback = ContractMethod::new();
+    resolve_function_storage(
+        start,
+        &registry,
+        Vec::new(),
+        &mut fallback,
+        false,
+        cycle_solver,
+        recognizer,
+        contract,
+        vec![0],
+        logger,
+    )?;
+    contract.fallback = Some(fallback);
+    contract.metadata = metadata;
+    Ok(())
+}
+
+/// Returns the function starting at the beginning of the code
+fn entry_point<'a, 'b>(
+    registry: &'b FunctionRegistry<'a>,
+    logger: &mut impl ContractLogger,
+) -> Result<&'b EvmFunction<'a>, AnalysisError> {
+    registry
+        .analyze(0, logger)
+        .ok_or(AnalysisError::InvalidJumpDestination {
+            pc: 0,
+            destination: 0,
+        })
+}
+
+/// Resolves the target of an internal call and retrives the function starting there
+fn resolve_jump<'a, 'b>(
+    call: &(StackValue, EvmStack, EvmMemory, Option<StackValue>, usize),
+    registry: &'b FunctionRegistry<'a>,
+    parent_data: &[(&EvmStack, &EvmMemory)],
+    logger: &mut impl ContractLogger,
+) -> Result<&'b EvmFunction<'a>, AnalysisError> {
+    let mut resolved = call.0.clone();
+    for parent in parent_data.iter().rev() {
+        resolved = resolved.replace_parent_call(parent.0, parent.1);
+    }
+    //println!("\t Resolved address: {:?}", resolved);
+    let address = match resolved.resolve() {
+        Some(address) => address,
+        None => {
+            return Err(AnalysisError::UnresolvableJump {
+                pc: call.4,
+                target: resolved,
+            })
+        }
+    };
+    if address > U256::from(usize::MAX) {
+        return Err(AnalysisError::InvalidJumpDestination {
+            pc: call.4,
+            destination: usize::MAX,
+        });
+    }
+    registry
+        .analyze(address.as_usize(), logger)
+        .ok_or(AnalysisError::InvalidJumpDestination {
+            pc: call.4,
+            destination: address.as_usize(),
+        })
+}
+
+/// Adds the accesses of `node` and of the blocks it calls to `contract_method`
+///
+/// Blocks that were not analyzed yet are executed with `logger`.
+pub fn resolve_function_storage<L: ContractLogger>(
     node: &EvmFunction,
     registry: &FunctionRegistry,
     parent_data: Vec<(&EvmStack, &EvmMemory)>,
     contract_method: &mut ContractMethod,
     top_level_found: bool,
     cycle_solver: &dyn CycleSolver,
-    storage: &mut HashMap<U256, ContractMethod>,
+    recognizer: &dyn DispatcherRecognizer,
+    contract: &mut ContractData,
     call_stack: Vec<usize>,
-) {
+    logger: &mut L,
+) -> Result<(), AnalysisError> {
+    if let Some(error) = &node.error {
+        if top_level_found {
+            // Keep what was found so far, the method will be treated conservatively
+            contract_method.mark_incomplete();
+            return Ok(());
+        }
+        return Err(error.clone());
+    }
+    // The analyzed code holds zeros where the constructor writes symbolic immutables
+    if contract
+        .immutables
+        .iter()
+        .any(|(position, _)| node.position <= *position && *position <= node.pc)
+    {
+        contract_method.mark_incomplete();
+    }
     //println!("RESOLVING NODE {} FOR STORAGE ACCESS", node.position);
     // println!("TOP LEVEL METHOD FOUND?: {}", top_level_found);
     // Resolve read access
@@ -122,37 +361,67 @@ pub fn resolve_function_storage(
         for parent in parent_data.iter().rev() {
             resolved_method = resolved_method.replace_parent_call(parent.0, parent.1);
         }
-        let resolved = (resolved_address, resolved_method);
+        let resolved = (resolved_address, resolved_method, read_access.2);
         //println!("Resolved value: {:?}", resolved);
         contract_method.push_external_call(resolved);
     }
-
-    for call in &node.internal_calls {
-        //println!("\t Analyzing call  to {:?}", call.0);
-        let mut resolved = call.0.clone();
+    // Resolve balance access
+    for account in &node.balance_read {
+        let mut resolved = account.clone();
+        for parent in parent_data.iter().rev() {
+            resolved = resolved.replace_parent_call(parent.0, parent.1);
+        }
+        contract_method.balance_read.insert(resolved);
+    }
+    for account in &node.balance_write {
+        let mut resolved = account.clone();
         for parent in parent_data.iter().rev() {
             resolved = resolved.replace_parent_call(parent.0, parent.1);
         }
-        //println!("\t Resolved address: {:?}", resolved);
-        let address = resolved.resolve().unwrap();
-        let new_node = &registry.analyzed[&address.as_usize()];
+        contract_method.balance_write.insert(resolved);
+    }
+    // Resolve contract creations
+    for creation in &node.contract_creations {
+        let mut resolved = creation.clone();
+        for parent in parent_data.iter().rev() {
+            resolved = resolved.replace_parent_call(parent.0, parent.1);
+        }
+        contract_method.push_contract_creation(resolved);
+    }
 
-        if !cycle_solver.should_go(
-            &call_stack,
-            address.as_usize(),
-            node.position,
-            registry,
-            &call.3,
-        ) {
+    // Accesses performed so far happen on every path leaving the dispatcher from here
+    let prefix = if top_level_found {
+        ContractMethod::new()
+    } else {
+        contract_method.clone()
+    };
+    for call in &node.internal_calls {
+        //println!("\t Analyzing call  to {:?}", call.0);
+        let new_node = match resolve_jump(call, registry, &parent_data, logger) {
+            Ok(new_node) => new_node,
+            Err(_) if top_level_found => {
+                contract_method.mark_incomplete();
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        let address = new_node.position;
+
+        if !cycle_solver.should_go(&call_stack, address, node.position, registry, &call.3) {
             continue;
         }
         let mut new_vector = parent_data.clone();
         new_vector.push((&call.1, &call.2));
 
         let mut newstack = call_stack.clone();
-        newstack.push(address.as_usize());
+        // Falling through a JUMPDEST continues the current block, so it does not count as entering
+        // the next one: a loop header reached this way can be entered again by the back edge
+        if call.4 != address {
+            newstack.push(address);
+        }
         // Check if we have found a top level method
         if !top_level_found {
+            let mut empty_calldata = None;
             if let Some(c) = &call.3 {
                 let mut resolved = c.clone();
                 /*let pdata: Vec<&Vec<StackValue>> = parent_data.iter().map(|v| &v.0.stack).collect();
@@ -160,11 +429,8 @@ pub fn resolve_function_storage(
                 for parent in parent_data.iter().rev() {
                     resolved = resolved.replace_parent_call(parent.0, parent.1);
                 }
-                if let Some(addr) = get_pubblic_method(&resolved,&parent_data) {
-                    let mut method = ContractMethod::new();
-                    method.access_read(contract_method.storage_read.clone());
-                    method.access_write(contract_method.storage_write.clone());
-                    method.method_calls(contract_method.method_call.clone());
+                if let Some(addr) = recognizer.get_method(&resolved, &new_vector) {
+                    let mut method = prefix.clone();
                     resolve_function_storage(
                         new_node,
                         registry,
@@ -172,13 +438,41 @@ pub fn resolve_function_storage(
                         &mut method,
                         true,
                         cycle_solver,
-                        storage,
+                        recognizer,
+                        contract,
                         newstack,
-                    );
-                    storage.insert(addr, method);
+                        logger,
+                    )?;
+                    contract.methods.insert(addr, method);
                     continue;
                 }
+                empty_calldata = get_calldata_size_guard(&resolved);
+            }
+            // Analyze the branch on its own, so that the methods dispatched inside it do not
+            // inherit the accesses of its siblings
+            let mut method = prefix.clone();
+            resolve_function_storage(
+                new_node,
+                registry,
+                new_vector,
+                &mut method,
+                false,
+                cycle_solver,
+                recognizer,
+                contract,
+                newstack,
+                logger,
+            )?;
+            if empty_calldata == Some(true) {
+                // The branch is only taken for empty calldata: it leads to the receive function
+                contract
+                    .receive
+                    .get_or_insert_with(ContractMethod::new)
+                    .merge(&method);
+            } else {
+                contract_method.merge(&method);
             }
+            continue;
         }
         resolve_function_storage(
             new_node,
@@ -187,63 +481,154 @@ pub fn resolve_function_storage(
             contract_method,
             top_level_found,
             cycle_solver,
-            storage,
+            recognizer,
+            contract,
             newstack,
-        );
+            logger,
+        )?;
     }
     //println!("END FUNCTION");
+    Ok(())
 }
 
-pub fn resolve_return_node(
+/// Finds the data returned by a function as (position of the `RETURN`, returned value, immutables,
+/// whether every write over a returned code section could be applied)
+///
+/// Blocks that were not analyzed yet are executed with `logger`.
+pub fn resolve_return_node<L: ContractLogger>(
     node: &EvmFunction,
     registry: &FunctionRegistry,
     parent_data: Vec<(&EvmStack, &EvmMemory)>,
-) -> Option<StackValue> {
+    logger: &mut L,
+) -> Result<Option<(usize, StackValue, Vec<(usize, StackValue)>, bool)>, AnalysisError> {
     //println!("RESOLVING NODE FOR RETURN: {}", node.position);
+    if let Some(error) = &node.error {
+        return Err(error.clone());
+    }
     if let Some(r) = &node.return_value {
         //println!("Found Return value: {:?}", r);
-        let mut replaced_start = r.1.clone();
-        let mut replaced_length = r.0.clone();
-        for parent in parent_data.iter().rev() {
-            replaced_start = replaced_start.replace_parent_call(parent.0, parent.1);
-            replaced_length = replaced_length.replace_parent_call(parent.0, parent.1);
+        let start = replace_parents(&r.1, &parent_data);
+        let length = replace_parents(&r.0, &parent_data);
+        // The memory of the node, followed by the memory of its parents from the innermost one;
+        // each comes with the parents needed to replace its placeholders
+        let mut levels = vec![(&node.memory, &parent_data[..])];
+        for i in (0..parent_data.len()).rev() {
+            levels.push((parent_data[i].1, &parent_data[..i]));
         }
-
-        if let Some(x) = node
-            .memory
-            .retrive(replaced_start.clone(), replaced_length.clone())
-        {
-            return Some(x);
-        }
-        for parent in parent_data.iter().rev() {
-            let mem_loc = parent
-                .1
-                .retrive(replaced_start.clone(), replaced_length.clone());
-            if let Some(x) = mem_loc {
-                return Some(x);
+        for (level, (memory, parents)) in levels.iter().enumerate() {
+            let entries = memory.entries();
+            let found = entries.iter().enumerate().rev().find(|(_, entry)| {
+                let offset = replace_parents(&entry.0, parents);
+                // Shorter writes at the same offset patch the returned data instead of replacing it
+                let entry_length = replace_parents(&entry.2, parents).resolve();
+                let covers = match (entry_length, length.resolve()) {
+                    (Some(entry_length), Some(length)) => entry_length >= length,
+                    _ => true,
+                };
+                covers
+                    && (offset == start
+                        || (offset.resolve().is_some() && offset.resolve() == start.resolve()))
+            });
+            if let Some((index, entry)) = found {
+                let value = replace_parents(&entry.1, parents);
+                if let (CodeSection(code), Some(offset)) = (&value, start.resolve()) {
+                    // Values written over the code after it was copied, from the oldest
+                    let mut patches = Vec::new();
+                    for (memory, parents) in levels[..level].iter().rev() {
+                        for entry in memory.entries() {
+                            patches.push((entry, *parents));
+                        }
+                    }
+                    let newer = entries[index + 1..].iter().map(|entry| (entry, *parents));
+                    let patches: Vec<_> = newer.chain(patches).collect();
+                    let (code, immutables, complete) = patch_code(code.clone(), offset, &patches);
+                    return Ok(Some((r.2, CodeSection(code), immutables, complete)));
+                }
+                return Ok(Some((r.2, value, Vec::new(), true)));
             }
         }
+        let value = MemoryPlaceHolder(Box::from(start), Box::from(length));
+        return Ok(Some((r.2, value, Vec::new(), true)));
     }
     for call in &node.internal_calls {
         //println!("\t Analyzing call  to {:?}", call.0);
-        let mut resolved = call.0.clone();
-        for parent in parent_data.iter().rev() {
-            resolved = resolved.replace_parent_call(parent.0, parent.1);
-        }
-        //println!("\t Resolved address: {:?}", resolved);
-        let address = resolved.resolve().unwrap();
-        let new_node = &registry.analyzed[&address.as_usize()];
+        let new_node = resolve_jump(call, registry, &parent_data, logger)?;
         let mut new_vector = parent_data.clone();
         new_vector.push((&call.1, &call.2));
-        let returned = resolve_return_node(new_node, registry, new_vector);
-        if let Some(x) = returned {
-            return Some(x);
+        let returned = resolve_return_node(new_node, registry, new_vector, logger)?;
+        if returned.is_some() {
+            return Ok(returned);
         }
     }
-    None
+    Ok(None)
+}
+
+/// Replaces the placeholders of a value with the data of its parents, from the innermost one
+fn replace_parents(value: &StackValue, parent_data: &[(&EvmStack, &EvmMemory)]) -> StackValue {
+    let mut replaced = value.clone();
+    for parent in parent_data.iter().rev() {
+        replaced = replaced.replace_parent_call(parent.0, parent.1);
+    }
+    replaced
 }
 
-/// List all function locations (defined by `JUMPDEST` and the starting position) inside the bytecode
+/// Applies the memory writes performed over a code section stored at `offset`
+///
+/// Constructors write the values of immutable variables inside the runtime code before returning
+/// it. Constant values are written into the code, while the positions of the values that are
+/// only known at deployment are returned together with the values. The returned flag is false
+/// when some write might overlap the code but could not be applied.
+fn patch_code(
+    mut code: Vec<u8>,
+    offset: U256,
+    patches: &[(
+        &(StackValue, StackValue, StackValue),
+        &[(&EvmStack, &EvmMemory)],
+    )],
+) -> (Vec<u8>, Vec<(usize, StackValue)>, bool) {
+    let mut immutables: Vec<(usize, StackValue)> = Vec::new();
+    let mut complete = true;
+    let end = offset.saturating_add(U256::from(code.len()));
+    for (entry, parents) in patches {
+        let position = replace_parents(&entry.0, parents).resolve();
+        let length = replace_parents(&entry.2, parents).resolve();
+        let (position, length) = match (position, length) {
+            (Some(position), Some(length)) => (position, length),
+            _ => {
+                complete = false;
+                continue;
+            }
+        };
+        if position >= end || position.saturating_add(length) <= offset {
+            continue;
+        }
+        let value = replace_parents(&entry.1, parents);
+        match value.resolve() {
+            Some(word) if length <= U256::from(32) => {
+                let mut bytes = [0u8; 32];
+                word.to_big_endian(&mut bytes);
+                let length = length.as_usize();
+                for (i, byte) in bytes[32 - length..].iter().enumerate() {
+                    let target = position + U256::from(i);
+                    if target >= offset && target < end {
+                        let target = (target - offset).as_usize();
+                        immutables.retain(|(p, _)| *p != target);
+                        code[target] = *byte;
+                    }
+                }
+            }
+            _ if position >= offset && length <= U256::from(32) => {
+                let position = (position - offset).as_usize();
+                immutables.retain(|(p, _)| *p != position);
+                immutables.push((position, value));
+            }
+            _ => complete = false,
+        }
+    }
+    (code, immutables, complete)
+}
+
+/// List all function locations (defined by `JUMPDEST`, `JUMPI` and the starting position) inside the bytecode
 pub fn list_functions(code: &[u8]) -> Vec<usize> {
     let mut list = vec![0];
     let mut pc = 0;
@@ -253,39 +638,14 @@ pub fn list_functions(code: &[u8]) -> Vec<usize> {
                 list.push(pc);
                 pc += 1;
             }
-            0x60 => pc += 1 + 1,
-            0x61 => pc += 2 + 1,
-            0x62 => pc += 3 + 1,
-            0x63 => pc += 4 + 1,
-            0x64 => pc += 5 + 1,
-            0x65 => pc += 6 + 1,
-            0x66 => pc += 7 + 1,
-            0x67 => pc += 8 + 1,
-            0x68 => pc += 9 + 1,
-            0x69 => pc += 10 + 1,
-            0x6a => pc += 11 + 1,
-            0x6b => pc += 12 + 1,
-            0x6c => pc += 13 + 1,
-            0x6d => pc += 14 + 1,
-            0x6e => pc += 15 + 1,
-            0x6f => pc += 16 + 1,
-            0x70 => pc += 17 + 1,
-            0x71 => pc += 18 + 1,
-            0x72 => pc += 19 + 1,
-            0x73 => pc += 20 + 1,
-            0x74 => pc += 21 + 1,
-            0x75 => pc += 22 + 1,
-            0x76 => pc += 23 + 1,
-            0x77 => pc += 24 + 1,
-            0x78 => pc += 25 + 1,
-            0x79 => pc += 26 + 1,
-            0x7a => pc += 27 + 1,
-            0x7b => pc += 28 + 1,
-            0x7c => pc += 29 + 1,
-            0x7d => pc += 30 + 1,
-            0x7e => pc += 31 + 1,
-            0x7f => pc += 32 + 1,
-            _ => pc += 1,
+            // The code following a conditional jump starts a new function
+            0x57 => {
+                if pc + 1 < code.len() && code[pc + 1] != 0x5b {
+                    list.push(pc + 1);
+                }
+                pc += 1;
+            }
+            opcode => pc += push_size(opcode) + 1,
         }
     }
 
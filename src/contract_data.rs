@@ -1,17 +1,42 @@
+use crate::abi::{AbiReport, MethodSignature};
+use crate::bytecode::Metadata;
 use crate::contract_utils::DataType;
-use crate::evm_types::StackValue;
+use crate::evm_types::{CallKind, ContractCreation, StackValue};
 use ethereum_types::U256;
+use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ContractData {
     pub constructor: ContractMethod,
     pub methods: HashMap<U256, ContractMethod>,
+    /// Code executed when no selector matches, `None` if it was not analyzed
+    pub fallback: Option<ContractMethod>,
+    /// Code executed for calls without calldata, `None` if the contract has no receive function
+    pub receive: Option<ContractMethod>,
+    /// Positions of the runtime code where the constructor writes values only known at deployment
+    /// (e.g. Solidity `immutable` variables), together with the written values
+    pub immutables: Vec<(usize, StackValue)>,
+    /// Metadata appended to the bytecode by the compiler
+    pub metadata: Option<Metadata>,
 }
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct ContractMethod {
     pub storage_read: HashSet<DataType>,
     pub storage_write: HashSet<DataType>,
-    pub method_call: HashSet<(StackValue, StackValue)>,
+    /// External calls as (contract address, calldata, call instruction)
+    pub method_call: HashSet<(StackValue, StackValue, CallKind)>,
+    /// Contracts deployed by the method
+    pub contract_creation: HashSet<ContractCreation>,
+    /// Accounts whose balance is read by the method
+    pub balance_read: HashSet<StackValue>,
+    /// Accounts whose balance might be modified by the method
+    pub balance_write: HashSet<StackValue>,
+    /// The method touches storage locations that could not be resolved to a known data structure
+    pub unknown_storage: bool,
+    /// The analysis of the method could not be completed (e.g. a jump target could not be resolved)
+    pub incomplete: bool,
+    /// Name and arguments of the method, when known from the ABI of the contract
+    pub signature: Option<MethodSignature>,
 }
 
 impl ContractData {
@@ -19,12 +44,20 @@ impl ContractData {
         ContractData {
             constructor: ContractMethod::new(),
             methods: HashMap::new(),
+            fallback: None,
+            receive: None,
+            immutables: Vec::new(),
+            metadata: None,
         }
     }
     pub fn construct(constructor: ContractMethod, methods: HashMap<U256, ContractMethod>) -> Self {
         ContractData {
             constructor,
             methods,
+            fallback: None,
+            receive: None,
+            immutables: Vec::new(),
+            metadata: None,
         }
     }
     ///
@@ -42,13 +75,74 @@ impl ContractData {
         self.constructor = method;
     }
     ///
+    /// Labels the methods with the functions of the ABI having the same selector
+    ///
+    /// Returns the functions of the ABI that were not found by the analysis and the selectors
+    /// found by the analysis that are not in the ABI.
+    ///
+    pub fn apply_abi(&mut self, functions: &[MethodSignature]) -> AbiReport {
+        let mut missing = Vec::new();
+        let mut labeled = HashSet::new();
+        for function in functions {
+            let selector = function.selector();
+            match self.methods.get_mut(&selector) {
+                Some(method) => {
+                    method.signature = Some(function.clone());
+                    labeled.insert(selector);
+                }
+                None => missing.push(function.clone()),
+            }
+        }
+        let mut unknown: Vec<U256> = self
+            .methods
+            .keys()
+            .filter(|selector| !labeled.contains(selector))
+            .cloned()
+            .collect();
+        unknown.sort();
+        AbiReport { missing, unknown }
+    }
+    ///
     /// Writes Information about the contract on console
     ///
     pub fn display(&self) {
         println!("\x1b[0;31m[===CONTRACT DATA===]\n");
+        if let Some(metadata) = &self.metadata {
+            println!("\x1b[0;33m[METADATA]\n\t\x1b[0m{:?}\n", metadata);
+        }
         println!("\x1b[0;33m[CONSTRUCTOR]\n\t\x1b[0;32mREAD:\x1b[0m{:?}\n\t\x1b[0;32mWRITE:\x1b[0m{:?}\n\n\t\x1b[0;32mCALLS:\x1b[0m{:?}\n",self.constructor.storage_read,self.constructor.storage_write,self.constructor.method_call);
         for method in &self.methods {
+            if let Some(signature) = &method.1.signature {
+                println!("\x1b[0;33m{}", signature);
+            }
             println!("\x1b[0;33mFUNCTION {:x}\n\t\x1b[0;32mREAD:\x1b[0m{:?}\n\t\x1b[0;32mWRITE:\x1b[0m{:?}\n\n\t\x1b[0;32mCALLS:\x1b[0m{:?}\n",method.0,method.1.storage_read,method.1.storage_write,method.1.method_call);
+            if !method.1.balance_read.is_empty() || !method.1.balance_write.is_empty() {
+                println!(
+                    "\t\x1b[0;32mBALANCE READ:\x1b[0m{:?}\n\t\x1b[0;32mBALANCE WRITE:\x1b[0m{:?}\n",
+                    method.1.balance_read, method.1.balance_write
+                );
+            }
+            if !method.1.contract_creation.is_empty() {
+                println!(
+                    "\t\x1b[0;32mCREATES:\x1b[0m{:?}\n",
+                    method.1.contract_creation
+                );
+            }
+            if method.1.is_conservative() {
+                println!(
+                    "\t\x1b[0;31mPARTIAL ANALYSIS:\x1b[0m unknown storage: {}, incomplete: {}\n",
+                    method.1.unknown_storage, method.1.incomplete
+                );
+            }
+        }
+        if let Some(fallback) = &self.fallback {
+            println!("\x1b[0;33m[FALLBACK]\n\t\x1b[0;32mREAD:\x1b[0m{:?}\n\t\x1b[0;32mWRITE:\x1b[0m{:?}\n\n\t\x1b[0;32mCALLS:\x1b[0m{:?}\n",fallback.storage_read,fallback.storage_write,fallback.method_call);
+        }
+        if !self.immutables.is_empty() {
+            println!("\x1b[0;33m[IMMUTABLES]\n\t\x1b[0m{:?}\n", self.immutables);
+        }
+        if let Some(receive) = &self.receive {
+            println!("\x1b[0;33m[RECEIVE]\n\t\x1b[0;32mREAD:\x1b[0m{:?}\n\t\x1b[0;32mWRITE:\x1b[0m{:?}\n\n\t\x1b[0;32mCALLS:\x1b[0m{:?}\n",receive.storage_read,receive.storage_write,receive.method_call);
         }
     }
 }
@@ -59,6 +153,12 @@ impl ContractMethod {
             storage_read: HashSet::new(),
             storage_write: HashSet::new(),
             method_call: HashSet::new(),
+            contract_creation: HashSet::new(),
+            balance_read: HashSet::new(),
+            balance_write: HashSet::new(),
+            unknown_storage: false,
+            incomplete: false,
+            signature: None,
         }
     }
     ///
@@ -71,6 +171,9 @@ impl ContractMethod {
     /// Adds a storage location that will be written during the execution of the method
     ///
     pub fn push_write_location(&mut self, access: DataType) {
+        if let DataType::Unknown(_) = access {
+            self.unknown_storage = true;
+        }
         self.storage_write.insert(access);
     }
     ///
@@ -83,18 +186,76 @@ impl ContractMethod {
     /// Adds a storage location that will be read during the execution of the method
     ///
     pub fn push_read_location(&mut self, access: DataType) {
+        if let DataType::Unknown(_) = access {
+            self.unknown_storage = true;
+        }
         self.storage_read.insert(access);
     }
     ///
     /// Adds external method calls that will be executed in this method
     ///
-    pub fn method_calls(&mut self, access: HashSet<(StackValue, StackValue)>) {
+    pub fn method_calls(&mut self, access: HashSet<(StackValue, StackValue, CallKind)>) {
         self.method_call.extend(access);
     }
     ///
     ///  Adds an external method call that will be executed in this method
     ///
-    pub fn push_external_call(&mut self, access: (StackValue, StackValue)) {
+    pub fn push_external_call(&mut self, access: (StackValue, StackValue, CallKind)) {
         self.method_call.insert(access);
     }
+    ///
+    /// Adds contract deployments performed by this method
+    ///
+    pub fn contract_creations(&mut self, creations: HashSet<ContractCreation>) {
+        self.contract_creation.extend(creations);
+    }
+    ///
+    /// Adds a contract deployment performed by this method
+    ///
+    pub fn push_contract_creation(&mut self, creation: ContractCreation) {
+        self.contract_creation.insert(creation);
+    }
+    ///
+    /// Adds accounts whose balance will be read during the execution of the method
+    ///
+    pub fn access_balance_read(&mut self, accounts: HashSet<StackValue>) {
+        self.balance_read.extend(accounts);
+    }
+    ///
+    /// Adds accounts whose balance might be modified during the execution of the method
+    ///
+    pub fn access_balance_write(&mut self, accounts: HashSet<StackValue>) {
+        self.balance_write.extend(accounts);
+    }
+    ///
+    /// Marks the analysis of this method as incomplete
+    ///
+    pub fn mark_incomplete(&mut self) {
+        self.incomplete = true;
+    }
+    ///
+    /// Copies the partial-analysis flags of another method into this one
+    ///
+    pub fn inherit_flags(&mut self, other: &ContractMethod) {
+        self.unknown_storage |= other.unknown_storage;
+        self.incomplete |= other.incomplete;
+    }
+    ///
+    /// Adds every access of another method to this one
+    ///
+    pub fn merge(&mut self, other: &ContractMethod) {
+        self.access_read(other.storage_read.clone());
+        self.access_write(other.storage_write.clone());
+        self.method_calls(other.method_call.clone());
+        self.contract_creations(other.contract_creation.clone());
+        self.access_balance_read(other.balance_read.clone());
+        self.access_balance_write(other.balance_write.clone());
+        self.inherit_flags(other);
+    }
+    ///
+    /// Whether the method must be considered as accessing every storage location of its contract
+    ///
+    pub fn is_conservative(&self) -> bool {
+        self.unknown_storage || self.incomplete
+    }
 }
@@ -9,6 +9,18 @@ pub trait ContractLogger {
     fn log_contract_data(&mut self, contract_data: ContractData);
 }
 
+/// A logger following the blocks analyzed on other threads
+///
+/// Each block analyzed by another thread is logged by a child of the logger, which is merged back
+/// into it once the block has been analyzed.
+pub trait ConcurrentLogger: ContractLogger {
+    type Child: ContractLogger + Send;
+    /// Creates the logger of a block analyzed by another thread
+    fn child(&self) -> Self::Child;
+    /// Adds what was logged by `child`
+    fn merge(&mut self, child: Self::Child);
+}
+
 pub struct NoLogger();
 
 impl ContractLogger for NoLogger {
@@ -18,3 +30,11 @@ impl ContractLogger for NoLogger {
     fn finalize(&mut self) {}
     fn log_contract_data(&mut self, _contract_data: ContractData) {}
 }
+
+impl ConcurrentLogger for NoLogger {
+    type Child = NoLogger;
+    fn child(&self) -> NoLogger {
+        NoLogger()
+    }
+    fn merge(&mut self, _child: NoLogger) {}
+}
@@ -3,8 +3,10 @@
 use crate::evm_types::StackValue;
 use crate::evm_types::StackValue::*;
 use ethereum_types::U256;
+use serde::{Deserialize, Serialize};
+use tiny_keccak::{Hasher, Keccak};
 ///Type of data that can be present in the contract storage
-#[derive(Debug, Hash, Eq, Clone, PartialEq)]
+#[derive(Debug, Hash, Eq, Clone, PartialEq, Serialize, Deserialize)]
 pub enum DataType {
     Field(StackValue),
     Struct(StackValue),
@@ -25,56 +27,48 @@ impl DataType {
     }
 }
 
-/// Return the root public method which this execution belongs to;
-pub fn get_pubblic_method(
-    guard: &StackValue,
-    stack: &Vec<(&crate::evm_memory::EvmStack, &crate::evm_memory::EvmMemory)>,
-) -> Option<U256> {
-    if let EQ(a, b) = guard {
-        // Method guards allways start with eq
-        //println!("looking for calldata in {:?}", guard);
-        //println!("{:?}", stack.last().unwrap().0);
-        if let ActualValue(x) = **a {
-            let hash = x;
-            if look_for_calldata(&*b) {
-                return Some(hash);
-            }
-        }
-        if let ActualValue(x) = **b {
-            let hash = x;
-            if look_for_calldata(&*a) {
-                return Some(hash);
-            }
-        }
+/// Computes the keccak256 hash of the given bytes
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak::v256();
+    let mut output = [0u8; 32];
+    hasher.update(data);
+    hasher.finalize(&mut output);
+    output
+}
+
+/// Recognises the guards of the dispatcher checking whether the calldata is empty
+///
+/// Returns `Some(true)` if the jump is taken only for empty calldata, `Some(false)` if it is
+/// taken only for non empty calldata.
+pub fn get_calldata_size_guard(guard: &StackValue) -> Option<bool> {
+    let is_value = |v: &StackValue, n: u64| v.resolve() == Some(U256::from(n));
+    match guard {
+        CallDataSize => Some(false),
+        IsZero(x) => get_calldata_size_guard(x).map(|empty| !empty),
+        EQ(a, b) if **a == CallDataSize && is_value(b, 0) => Some(true),
+        EQ(a, b) if **b == CallDataSize && is_value(a, 0) => Some(true),
+        // CALLDATASIZE < 1
+        LT(a, b) if **a == CallDataSize && is_value(b, 1) => Some(true),
+        GT(a, b) if **b == CallDataSize && is_value(a, 1) => Some(true),
+        // CALLDATASIZE > 0
+        GT(a, b) if **a == CallDataSize && is_value(b, 0) => Some(false),
+        LT(a, b) if **b == CallDataSize && is_value(a, 0) => Some(false),
+        _ => None,
     }
-    None
 }
 
 /// looks for a CallDataLoad(0) inside a StackValue tree
 pub fn look_for_calldata(val: &StackValue) -> bool {
-    match val {
-        CallDataLoad(x) => {
-            if let ActualValue(v) = **x {
-                v == U256::from(0)
-            } else {
-                false
-            }
-        }
-        And(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
-        Xor(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
-        Or(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
-        Add(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
-        Sub(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
-        Mul(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
-        Div(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
-        Shr(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
-        ShL(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
-        GT(a, b) => look_for_calldata(&**a) || look_for_calldata(&**b),
+    val.contains(&|v| match v {
+        CallDataLoad(x) => x.resolve() == Some(U256::zero()),
         _ => false,
-    }
+    })
 }
 
 /// Retrives the "top level" data structure this storage location belongs to
+///
+/// Only the operands of `Add` and `Sha3` are inspected, any other expression is `Unknown` as a
+/// whole.
 pub fn top_level_data(expr: &StackValue) -> DataType {
     match expr {
         ActualValue(_) => DataType::Field(expr.clone()),
@@ -92,7 +86,7 @@ pub fn top_level_data(expr: &StackValue) -> DataType {
             }
         }
 
-        Sha3(v) => {
+        Sha3(_, _, v) => {
             if v.len() == 1 {
                 // Array
                 return DataType::Vector(v[0].1.clone());
@@ -1,6 +1,10 @@
 use crate::contract_utils::{top_level_data, DataType};
 use crate::evm_function::{EvmFunction, FunctionRegistry};
 use crate::evm_types::StackValue;
+/// Decides which jumps are followed while resolving the storage accesses of a method
+///
+/// Implementations can query the control flow graph of the code through `registry.cfg()`, e.g. to
+/// check whether `address` is a loop header.
 pub trait CycleSolver {
     fn get_data(&self, location: &StackValue) -> DataType;
     fn should_go(
@@ -14,7 +18,11 @@ pub trait CycleSolver {
 }
 
 pub struct NocycleSolver();
-pub struct MaxIterations(usize);
+/// Follows each loop of the control flow graph up to the given number of times
+///
+/// Loop headers can appear on the path that many times; any other block is entered again only
+/// after going around a loop, i.e. when a loop header was entered since its last occurrence.
+pub struct MaxIterations(pub usize);
 
 impl CycleSolver for MaxIterations {
     fn get_data(&self, location: &StackValue) -> DataType {
@@ -25,7 +33,7 @@ impl CycleSolver for MaxIterations {
         stack: &Vec<usize>,
         address: usize,
         _prev_address: usize,
-        _registry: &FunctionRegistry,
+        registry: &FunctionRegistry,
         _condition: &Option<StackValue>,
     ) -> bool {
         /*let jumps = &registry.get_from_address(address).unwrap().internal_calls;
@@ -42,11 +50,15 @@ impl CycleSolver for MaxIterations {
             !stack.contains(&address)
         }*/
         //println!("STACK {:?}",stack);
-        let filtered: Vec<&usize> = stack.iter().filter(|a| (**a) == address).collect();
-        //println!("FILTERED {:?}",filtered);
-        //println!("JUMP {}",(filtered.len() > 50));
-        !(filtered.len() > self.0)
-        // !stack.contains(&address)
+        let cfg = registry.cfg();
+        if cfg.is_loop_header(address) {
+            let filtered: Vec<&usize> = stack.iter().filter(|a| (**a) == address).collect();
+            return !(filtered.len() > self.0);
+        }
+        match stack.iter().rposition(|a| *a == address) {
+            Some(last) => stack[last + 1..].iter().any(|a| cfg.is_loop_header(*a)),
+            None => true,
+        }
     }
 }
 
@@ -0,0 +1,142 @@
+use crate::contract_utils::look_for_calldata;
+use crate::evm_memory::{EvmMemory, EvmStack};
+use crate::evm_types::{StackValue, StackValue::*};
+use ethereum_types::U256;
+
+/// Recognises the guards used by the dispatcher of a contract to select a public method
+///
+/// Guards are the conditions of `JUMPI` instructions; `resolve_function_storage` attributes the
+/// code reached by a jump to the method whose selector is returned by `get_method`.
+pub trait DispatcherRecognizer: Sync {
+    /// Whether `value` is the selector of the call, as extracted from the calldata by the dispatcher
+    ///
+    /// `parent_data` contains the stack and memory of the functions executed to reach the guard,
+    /// the last one belonging to the function performing the jump.
+    fn is_selector(&self, value: &StackValue, parent_data: &[(&EvmStack, &EvmMemory)]) -> bool;
+
+    /// Returns the selector of the method reached when `guard` holds
+    fn get_method(
+        &self,
+        guard: &StackValue,
+        parent_data: &[(&EvmStack, &EvmMemory)],
+    ) -> Option<U256> {
+        let (selector, value) = compared_constant(guard)?;
+        if self.is_selector(value, parent_data) {
+            Some(selector)
+        } else {
+            None
+        }
+    }
+}
+
+/// Selector extracted with `SHR(224, CALLDATALOAD(0))`
+///
+/// Used by solc >= 0.5 (including the binary search dispatcher for contracts with many methods),
+/// Vyper >= 0.3.4 and most Huff contracts.
+pub struct ShrDispatcher();
+
+/// Selector extracted with `DIV(CALLDATALOAD(0), 2^224)`, used by solc < 0.5
+pub struct DivDispatcher();
+
+/// Selector stored with `MSTORE(28, CALLDATALOAD(0))` and read back with `MLOAD(0)`, used by Vyper < 0.3.4
+pub struct VyperDispatcher();
+
+/// Any expression computed from `CALLDATALOAD(0)`
+///
+/// This is the most permissive recognizer, matching hand written dispatchers with unusual masks.
+/// It is not tried by `DefaultDispatcher`, since it also accepts values that are not selectors.
+pub struct CalldataDispatcher();
+
+/// Tries the recognizers of the solc and Vyper dispatchers
+pub struct DefaultDispatcher();
+
+impl DispatcherRecognizer for ShrDispatcher {
+    fn is_selector(&self, value: &StackValue, _parent_data: &[(&EvmStack, &EvmMemory)]) -> bool {
+        match unmask(value) {
+            Shr(shift, word) => is_value(shift, 224) && is_calldata_head(word),
+            _ => false,
+        }
+    }
+}
+
+impl DispatcherRecognizer for DivDispatcher {
+    fn is_selector(&self, value: &StackValue, _parent_data: &[(&EvmStack, &EvmMemory)]) -> bool {
+        match unmask(value) {
+            Div(word, divisor) => {
+                is_calldata_head(word) && divisor.resolve() == Some(U256::one() << 224)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl DispatcherRecognizer for VyperDispatcher {
+    fn is_selector(&self, value: &StackValue, parent_data: &[(&EvmStack, &EvmMemory)]) -> bool {
+        if let MemoryPlaceHolder(offset, _) = value {
+            if !is_value(offset, 0) {
+                return false;
+            }
+            // The last 4 bytes of the word at 0 are the first 4 bytes of the calldata
+            let head = ActualValue(U256::from(28));
+            return parent_data.iter().any(|(_, memory)| {
+                is_calldata_head(&memory.retrive(head.clone(), ActualValue(U256::from(32))))
+            });
+        }
+        false
+    }
+}
+
+impl DispatcherRecognizer for CalldataDispatcher {
+    fn is_selector(&self, value: &StackValue, _parent_data: &[(&EvmStack, &EvmMemory)]) -> bool {
+        look_for_calldata(value)
+    }
+}
+
+impl DispatcherRecognizer for DefaultDispatcher {
+    fn is_selector(&self, value: &StackValue, parent_data: &[(&EvmStack, &EvmMemory)]) -> bool {
+        ShrDispatcher().is_selector(value, parent_data)
+            || DivDispatcher().is_selector(value, parent_data)
+            || VyperDispatcher().is_selector(value, parent_data)
+    }
+}
+
+/// Splits a guard holding when a value equals a constant into the constant and the value
+///
+/// Besides `EQ`, dispatchers test for equality with `ISZERO(XOR(..))` and `ISZERO(SUB(..))`; the
+/// fall-through branch of a `JUMPI` is guarded by the negated condition.
+pub fn compared_constant(guard: &StackValue) -> Option<(U256, &StackValue)> {
+    let (a, b) = match guard {
+        EQ(a, b) => (a, b),
+        IsZero(x) => match &**x {
+            Xor(a, b) | Sub(a, b) => (a, b),
+            IsZero(y) => return compared_constant(y),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match (&**a, &**b) {
+        (ActualValue(x), value) | (value, ActualValue(x)) => Some((*x, value)),
+        _ => None,
+    }
+}
+
+/// Removes the `AND(0xffffffff, ..)` mask applied to the selector by some compilers
+fn unmask(value: &StackValue) -> &StackValue {
+    match value {
+        And(a, b) if is_value(a, 0xffffffff) => b,
+        And(a, b) if is_value(b, 0xffffffff) => a,
+        _ => value,
+    }
+}
+
+/// Whether the value is the first word of the calldata
+fn is_calldata_head(value: &StackValue) -> bool {
+    match value {
+        CallDataLoad(offset) => is_value(offset, 0),
+        _ => false,
+    }
+}
+
+fn is_value(value: &StackValue, n: u64) -> bool {
+    value.resolve() == Some(U256::from(n))
+}
@@ -1,6 +1,11 @@
+use crate::analysis_error::AnalysisError;
+use crate::cfg::Cfg;
+use crate::contract_logger::{ContractLogger, NoLogger};
 use crate::evm_memory::{EvmMemory, EvmStack};
-use crate::evm_types::StackValue;
+use crate::evm_types::{CallKind, ContractCreation, StackValue};
+use crate::function_analyzer::AnalysisStats;
 use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
 
 /// A Self contained block of instructions in the evm bytecode
 ///
@@ -30,8 +35,8 @@ pub struct EvmFunction<'a> {
     /// The program counter keeping trak of the current position in the code
     pub pc: usize,
 
-    /// The memory area returned by this function
-    pub return_value: Option<(StackValue, StackValue)>,
+    /// The memory area returned by this function, expressed as (length, offset), and the position of the `RETURN` instruction
+    pub return_value: Option<(StackValue, StackValue, usize)>,
 
     /// The list of other internal (belonging to this contract) functions that might be called during the execution of this one
     ///
@@ -44,7 +49,8 @@ pub struct EvmFunction<'a> {
     /// 2. Evm Stack at the moment of the call
     /// 3. Evm Memory at the moment of the call
     /// 4. Condition required to make the jump (None for unconditional jumps)
-    pub internal_calls: Vec<(StackValue, EvmStack, EvmMemory, Option<StackValue>)>,
+    /// 5. Position of the jump instruction
+    pub internal_calls: Vec<(StackValue, EvmStack, EvmMemory, Option<StackValue>, usize)>,
 
     /// The list of internal storage locations accessed by this function for reading
     pub storage_access_read: HashSet<StackValue>,
@@ -53,7 +59,24 @@ pub struct EvmFunction<'a> {
     pub storage_access_write: HashSet<StackValue>,
 
     /// The list of external (belonging to other contracts) functions invoked by this one
-    pub external_calls: HashSet<(StackValue, StackValue)>,
+    ///
+    /// The values memorized in the tuple are the following:
+    /// 1. Address of the called contract
+    /// 2. Calldata sent to the contract
+    /// 3. Instruction used for the call
+    pub external_calls: HashSet<(StackValue, StackValue, CallKind)>,
+
+    /// The list of contracts deployed by this function
+    pub contract_creations: HashSet<ContractCreation>,
+
+    /// The list of accounts whose balance is read by this function
+    pub balance_read: HashSet<StackValue>,
+
+    /// The list of accounts whose balance might be modified by this function
+    pub balance_write: HashSet<StackValue>,
+
+    /// The error that stopped the execution of this function, if any
+    pub error: Option<AnalysisError>,
 }
 
 impl<'a> EvmFunction<'a> {
@@ -68,25 +91,98 @@ impl<'a> EvmFunction<'a> {
             return_value: None,
             internal_calls: Vec::new(),
             external_calls: HashSet::new(),
+            contract_creations: HashSet::new(),
+            balance_read: HashSet::new(),
+            balance_write: HashSet::new(),
             storage_access_read: HashSet::new(),
             storage_access_write: HashSet::new(),
+            error: None,
         }
     }
 }
+/// The blocks of a bytecode, each one analyzed the first time it is requested
+///
+/// The registry can be shared between threads: every block is executed at most once, and the threads
+/// requesting a block while it is being analyzed wait for the result.
 pub struct FunctionRegistry<'a> {
-    pub analyzed: HashMap<usize, EvmFunction<'a>>,
-    pub in_analysis: Vec<usize>,
+    code: &'a [u8],
+    /// Blocks by starting position, empty until analyzed
+    blocks: HashMap<usize, OnceLock<EvmFunction<'a>>>,
+    cfg: OnceLock<Cfg>,
 }
 
 impl<'a> FunctionRegistry<'a> {
-    pub fn new() -> Self {
+    /// Registry of the blocks of `code` starting at the positions in `functions`
+    pub fn new(code: &'a [u8], functions: &[usize]) -> Self {
         FunctionRegistry {
-            analyzed: HashMap::new(),
-            in_analysis: Vec::new(),
+            code,
+            blocks: functions.iter().map(|f| (*f, OnceLock::new())).collect(),
+            cfg: OnceLock::new(),
         }
     }
 
+    /// Returns the block starting at `addr`, executing it with `logger` if not yet analyzed
+    ///
+    /// Returns None when no block of the registry starts at `addr`.
+    pub fn analyze(
+        &self,
+        addr: usize,
+        logger: &mut impl ContractLogger,
+    ) -> Option<&EvmFunction<'a>> {
+        let block = self.blocks.get(&addr)?;
+        Some(block.get_or_init(|| {
+            logger.log_new_function(addr);
+            let mut evm_func = EvmFunction::new(addr, self.code);
+            evm_func.execute(logger);
+            logger.finalize_function(&evm_func);
+            evm_func
+        }))
+    }
+
+    /// Returns the block starting at `addr` like `analyze`, without logging its execution
     pub fn get_from_address(&self, addr: usize) -> Option<&EvmFunction<'a>> {
-        self.analyzed.get(&addr)
+        self.analyze(addr, &mut NoLogger())
+    }
+
+    /// Whether the block starting at `addr` has already been executed
+    pub fn is_analyzed(&self, addr: usize) -> bool {
+        self.blocks
+            .get(&addr)
+            .is_some_and(|block| block.get().is_some())
+    }
+
+    /// The blocks executed so far, in no particular order
+    pub fn analyzed(&self) -> impl Iterator<Item = &EvmFunction<'a>> {
+        self.blocks.values().filter_map(|block| block.get())
+    }
+
+    /// Starting positions of the blocks not executed so far, in ascending order
+    pub fn skipped(&self) -> Vec<usize> {
+        let mut skipped: Vec<usize> = self
+            .blocks
+            .iter()
+            .filter(|(_, block)| block.get().is_none())
+            .map(|(addr, _)| *addr)
+            .collect();
+        skipped.sort();
+        skipped
+    }
+
+    /// Number of analyzed and skipped blocks
+    pub fn stats(&self) -> AnalysisStats {
+        let analyzed = self.analyzed().count();
+        AnalysisStats {
+            listed: self.blocks.len(),
+            analyzed,
+            skipped: self.blocks.len() - analyzed,
+        }
+    }
+
+    /// The control flow graph of the blocks reachable from the start of the code, built the first
+    /// time it is requested
+    ///
+    /// Building the graph analyzes the blocks it reaches.
+    pub fn cfg(&self) -> &Cfg {
+        self.cfg.get_or_init(|| Cfg::new(self))
     }
 }
@@ -1,11 +1,17 @@
+use crate::analysis_error::AnalysisError;
 use crate::contract_logger::ContractLogger;
 use crate::cycle_resolution::CycleSolver;
 use crate::evm_function::EvmFunction;
-use crate::evm_types::{StackValue, StackValue::*};
+use crate::evm_types::{CallKind, ContractCreation, StackValue, StackValue::*};
 use ethereum_types::U256;
 impl<'a> EvmFunction<'a> {
     pub fn execute(&mut self, logger: &mut impl ContractLogger) {
         while !self.ended {
+            if self.pc >= self.code.len() {
+                // Running past the end of the code is an implicit STOP
+                self.ended = true;
+                break;
+            }
             let opcode = self.code[self.pc];
             match opcode {
                 0x0 => self.stop(logger),
@@ -57,6 +63,7 @@ impl<'a> EvmFunction<'a> {
                 0x43 => self.number(logger),
                 0x44 => self.difficulty(logger),
                 0x45 => self.gaslimit(logger),
+                0x47 => self.selfbalance(logger),
                 0x50 => self.pop(logger),
                 0x51 => self.mload(logger),
                 0x52 => self.mstore(logger),
@@ -69,6 +76,7 @@ impl<'a> EvmFunction<'a> {
                 0x59 => self.msize(logger),
                 0x5a => self.gas(logger),
                 0x5b => self.jumpdest(logger),
+                0x5f => self.push0(logger),
                 0x60 => self.push(1, logger),
                 0x61 => self.push(2, logger),
                 0x62 => self.push(3, logger),
@@ -140,7 +148,7 @@ impl<'a> EvmFunction<'a> {
                 0xa4 => self.log(4, logger),
                 0xf0 => self.create(logger),
                 0xf1 => self.call(logger),
-                0xf2 => self.call(logger),
+                0xf2 => self.call_code(logger),
                 0xf3 => self.return_(logger),
                 0xf4 => self.delegate_call(logger),
                 0xf5 => self.create2(logger),
@@ -330,10 +338,13 @@ impl<'a> EvmFunction<'a> {
         let mut hasher= Keccak256::new();
         hasher.update(&value[..]);
         self.stack.push(ActualValue(U256::from(&hasher.finalize()[..])));*/
-        let value = self
-            .memory
-            .retrive_array(op1.resolve().unwrap(), op2.resolve().unwrap());
-        self.stack.push(Sha3(value));
+        if let (Some(offset), Some(length)) = (op1.resolve(), op2.resolve()) {
+            let value = self.memory.retrive_array(offset, length);
+            self.stack.push(Sha3(offset, length, value));
+        } else {
+            self.error = Some(AnalysisError::SymbolicSha3 { pc: self.pc });
+            self.ended = true;
+        }
     }
     pub fn address(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "ADDRESS");
@@ -342,6 +353,7 @@ impl<'a> EvmFunction<'a> {
     pub fn balance(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "BALANCE");
         let address = self.stack.pop();
+        self.balance_read.insert(address.clone());
         self.stack.push(Balance(Box::from(address)));
     }
     pub fn origin(&mut self, logger: &mut impl ContractLogger) {
@@ -385,15 +397,20 @@ impl<'a> EvmFunction<'a> {
         let length = self.stack.pop();
         let length2 = length.clone();
         let value: StackValue;
-        if let Some(a) = code_offset.resolve() {
-            if let Some(b) = length.resolve() {
-                if a.as_usize() + b.as_usize() <= self.code.len() {
-                    value = CodeSection(Vec::from(
-                        &self.code[a.as_usize()..a.as_usize() + b.as_usize()],
-                    ));
-                    self.memory.store(dest_offset, value, length2);
-                    return;
-                }
+        let code_size = U256::from(self.code.len());
+        let resolved_length = match &length {
+            // The length of code placed at the end of the bytecode is computed from CODESIZE,
+            // which is taken as the size of the analyzed code
+            Sub(a, b) if **a == CodeSize => b.resolve().map(|x| code_size.saturating_sub(x)),
+            _ => length.resolve(),
+        };
+        if let (Some(a), Some(b)) = (code_offset.resolve(), resolved_length) {
+            if a <= code_size && b <= code_size - a {
+                value = CodeSection(Vec::from(
+                    &self.code[a.as_usize()..a.as_usize() + b.as_usize()],
+                ));
+                self.memory.store(dest_offset, value, length2);
+                return;
             }
         }
         value = CodeCopy(Box::from(code_offset), Box::from(length));
@@ -463,6 +480,11 @@ impl<'a> EvmFunction<'a> {
         self.log_operation(logger, "GASLIMIT");
         self.stack.push(GasLimit);
     }
+    pub fn selfbalance(&mut self, logger: &mut impl ContractLogger) {
+        self.log_operation(logger, "SELFBALANCE");
+        self.balance_read.insert(Address);
+        self.stack.push(Balance(Box::from(Address)));
+    }
     pub fn pop(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "POP");
         self.stack.pop();
@@ -470,17 +492,8 @@ impl<'a> EvmFunction<'a> {
     pub fn mload(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "MLOAD");
         let offset = self.stack.pop();
-        let sv = self
-            .memory
-            .retrive(offset.clone(), ActualValue(U256::from(32)));
-        if let Some(av) = sv {
-            self.stack.push(av);
-        } else {
-            self.stack.push(MemoryPlaceHolder(
-                Box::from(offset),
-                Box::from(ActualValue(U256::from(32))),
-            ))
-        }
+        let sv = self.memory.retrive(offset, ActualValue(U256::from(32)));
+        self.stack.push(sv);
     }
     pub fn mstore(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "MSTORE");
@@ -516,8 +529,13 @@ impl<'a> EvmFunction<'a> {
         }*/
         let jmp_address = self.stack.pop();
         self.ended = true;
-        self.internal_calls
-            .push((jmp_address, self.stack.clone(), self.memory.clone(), None))
+        self.internal_calls.push((
+            jmp_address,
+            self.stack.clone(),
+            self.memory.clone(),
+            None,
+            self.pc,
+        ))
     }
     pub fn jumpi(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "JUMPI");
@@ -538,8 +556,20 @@ impl<'a> EvmFunction<'a> {
             jmp_address,
             self.stack.clone(),
             self.memory.clone(),
-            Some(condition),
+            Some(condition.clone()),
+            self.pc,
         ));
+        // The code following the jump is a separate function, reached when the condition is false
+        self.ended = true;
+        if self.pc + 1 < self.code.len() {
+            self.internal_calls.push((
+                ActualValue(U256::from(self.pc + 1)),
+                self.stack.clone(),
+                self.memory.clone(),
+                Some(IsZero(Box::from(condition))),
+                self.pc,
+            ));
+        }
     }
     pub fn pc(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "PC");
@@ -555,6 +585,18 @@ impl<'a> EvmFunction<'a> {
     }
     pub fn jumpdest(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "JUMPDEST");
+        // Falling through into another function is an unconditional jump to it
+        if self.pc != self.position {
+            self.ended = true;
+            self.internal_calls.push((
+                ActualValue(U256::from(self.pc)),
+                self.stack.clone(),
+                self.memory.clone(),
+                None,
+                self.pc,
+            ));
+            return;
+        }
         /*if self.jumped {
             self.jumped = false;
             return;
@@ -562,11 +604,21 @@ impl<'a> EvmFunction<'a> {
         self.maybecycle.insert(self.pc);*/
     }
     pub fn push(&mut self, length: usize, logger: &mut impl ContractLogger) {
+        if self.pc + length >= self.code.len() {
+            self.log_operation(logger, "PUSH");
+            self.error = Some(AnalysisError::TruncatedPush { pc: self.pc });
+            self.ended = true;
+            return;
+        }
         let value = &self.code[self.pc + 1..(self.pc + length + 1)];
         self.log_operation(logger, &format!("PUSH({:x})", U256::from(value))[..]);
         self.pc += length;
         self.stack.push(ActualValue(U256::from(value)));
     }
+    pub fn push0(&mut self, logger: &mut impl ContractLogger) {
+        self.log_operation(logger, "PUSH0");
+        self.stack.push(ActualValue(U256::zero()));
+    }
     pub fn dup(&mut self, n: usize, logger: &mut impl ContractLogger) {
         self.log_operation(logger, &format!("DUP {}", n)[..]);
         let value = self.stack.clone_pos(n);
@@ -589,6 +641,12 @@ impl<'a> EvmFunction<'a> {
         let op1 = self.stack.pop();
         let op2 = self.stack.pop();
         let op3 = self.stack.pop();
+        let init_code = self.init_code(&op2, &op3);
+        self.contract_creations
+            .insert(ContractCreation::Create(init_code));
+        if transfers_value(&op1) {
+            self.balance_write.insert(Address);
+        }
         self.stack
             .push(Create(Box::from(op1), Box::from(op2), Box::from(op3)));
     }
@@ -601,10 +659,12 @@ impl<'a> EvmFunction<'a> {
         let mem_length = self.stack.pop();
         let op6 = self.stack.pop();
         let op7 = self.stack.pop();
-        let mem_value = self.memory.retrive(offset.clone(), mem_length.clone());
         //println!("INSERTING INTO CALL");
-        self.external_calls
-            .insert((address.clone(), mem_value.unwrap()));
+        self.push_external_call(CallKind::Call, &address, &offset, &mem_length);
+        if transfers_value(&op3) {
+            self.balance_write.insert(Address);
+            self.balance_write.insert(address.clone());
+        }
         //println!("{:?}", self.external_calls);
         self.stack.push(Call(
             Box::from(op1),
@@ -616,26 +676,70 @@ impl<'a> EvmFunction<'a> {
             Box::from(op7),
         ))
     }
+    pub fn call_code(&mut self, logger: &mut impl ContractLogger) {
+        self.log_operation(logger, "CALLCODE");
+        let op1 = self.stack.pop();
+        let address = self.stack.pop();
+        let op3 = self.stack.pop();
+        let offset = self.stack.pop();
+        let mem_length = self.stack.pop();
+        let op6 = self.stack.pop();
+        let op7 = self.stack.pop();
+        self.push_external_call(CallKind::CallCode, &address, &offset, &mem_length);
+        self.stack.push(CallCode(
+            Box::from(op1),
+            Box::from(address),
+            Box::from(op3),
+            Box::from(offset),
+            Box::from(mem_length),
+            Box::from(op6),
+            Box::from(op7),
+        ))
+    }
+    /// Records an external call together with the calldata stored in memory
+    fn push_external_call(
+        &mut self,
+        kind: CallKind,
+        address: &StackValue,
+        offset: &StackValue,
+        length: &StackValue,
+    ) {
+        let mem_value = self.memory.retrive(offset.clone(), length.clone());
+        self.external_calls
+            .insert((address.clone(), mem_value, kind));
+    }
+    /// Retrives the init code of a deployed contract from memory
+    ///
+    /// The code is only considered known if the memory area contains exactly one code section.
+    fn init_code(&self, offset: &StackValue, length: &StackValue) -> StackValue {
+        let value = self.memory.retrive(offset.clone(), length.clone());
+        match (&value, length.resolve()) {
+            (CodeSection(code), Some(l)) if U256::from(code.len()) == l => value,
+            (CodeSection(_), _) => CodeCopy(Box::from(offset.clone()), Box::from(length.clone())),
+            _ => value,
+        }
+    }
     pub fn return_(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "RETURN");
         let op1 = self.stack.pop();
         let op2 = self.stack.pop();
-        self.return_value = Some((op2, op1));
+        self.return_value = Some((op2, op1, self.pc));
         self.ended = true;
     }
     pub fn delegate_call(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "DELEGATECALL");
         let op1 = self.stack.pop();
-        let op2 = self.stack.pop();
-        let op3 = self.stack.pop();
-        let op4 = self.stack.pop();
+        let address = self.stack.pop();
+        let offset = self.stack.pop();
+        let mem_length = self.stack.pop();
         let op5 = self.stack.pop();
         let op6 = self.stack.pop();
+        self.push_external_call(CallKind::DelegateCall, &address, &offset, &mem_length);
         self.stack.push(DelegateCall(
             Box::from(op1),
-            Box::from(op2),
-            Box::from(op3),
-            Box::from(op4),
+            Box::from(address),
+            Box::from(offset),
+            Box::from(mem_length),
             Box::from(op5),
             Box::from(op6),
         ))
@@ -646,6 +750,12 @@ impl<'a> EvmFunction<'a> {
         let op2 = self.stack.pop();
         let op3 = self.stack.pop();
         let op4 = self.stack.pop();
+        let init_code = self.init_code(&op2, &op3);
+        self.contract_creations
+            .insert(ContractCreation::Create2(init_code, op4.clone()));
+        if transfers_value(&op1) {
+            self.balance_write.insert(Address);
+        }
         self.stack.push(Create2(
             Box::from(op1),
             Box::from(op2),
@@ -656,16 +766,17 @@ impl<'a> EvmFunction<'a> {
     pub fn static_call(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "STATICCALL");
         let op1 = self.stack.pop();
-        let op2 = self.stack.pop();
-        let op3 = self.stack.pop();
-        let op4 = self.stack.pop();
+        let address = self.stack.pop();
+        let offset = self.stack.pop();
+        let mem_length = self.stack.pop();
         let op5 = self.stack.pop();
         let op6 = self.stack.pop();
+        self.push_external_call(CallKind::StaticCall, &address, &offset, &mem_length);
         self.stack.push(StaticCall(
             Box::from(op1),
-            Box::from(op2),
-            Box::from(op3),
-            Box::from(op4),
+            Box::from(address),
+            Box::from(offset),
+            Box::from(mem_length),
             Box::from(op5),
             Box::from(op6),
         ))
@@ -676,6 +787,15 @@ impl<'a> EvmFunction<'a> {
     }
     pub fn selfdestruct(&mut self, logger: &mut impl ContractLogger) {
         self.log_operation(logger, "SELFDESTRUCT");
+        let beneficiary = self.stack.pop();
+        // The whole balance is moved to the beneficiary
+        self.balance_write.insert(Address);
+        self.balance_write.insert(beneficiary);
         self.ended = true;
     }
 }
+
+/// Whether an amount of wei might be different from zero
+fn transfers_value(value: &StackValue) -> bool {
+    value.resolve().is_none_or(|v| !v.is_zero())
+}
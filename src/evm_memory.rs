@@ -29,22 +29,28 @@ impl EvmMemory {
         self.actual_memory.push((offset, value, length));
         //self.print_memory();
     }
-    pub fn retrive(&self, offset: StackValue, l: StackValue) -> Option<StackValue> {
+    /// Retrives the value stored at `offset`, or a placeholder for the memory of the caller
+    pub fn retrive(&self, offset: StackValue, l: StackValue) -> StackValue {
         //self.print_memory();
         for el in self.actual_memory.iter().rev() {
             if el.0 == offset {
-                return Some(el.1.clone());
+                return el.1.clone();
             }
         }
-        Some(StackValue::MemoryPlaceHolder(
-            Box::from(offset),
-            Box::from(l),
-        ))
+        StackValue::MemoryPlaceHolder(Box::from(offset), Box::from(l))
+    }
+    /// Stored values as (offset, value, length), from the oldest to the most recent
+    pub fn entries(&self) -> &[(StackValue, StackValue, StackValue)] {
+        &self.actual_memory
     }
     /// Attempt to retrive more than one consecutive memory position; this is only possible if the memory offsets and lengths can be resolved as U256
+    ///
+    /// Only the 32 bytes words inside the area and not overwritten by later stores are returned.
     pub fn retrive_array(&self, offset: U256, length: U256) -> Vec<(usize, StackValue)> {
         //self.print_memory();
         let mut vector: Vec<(usize, StackValue)> = Vec::new();
+        // Areas written after the current element
+        let mut written: Vec<(U256, U256)> = Vec::new();
         for el in self.actual_memory.iter().rev() {
             let pos = if let Some(x) = el.0.resolve() {
                 x
@@ -56,7 +62,17 @@ impl EvmMemory {
             } else {
                 continue;
             };
-            if pos >= offset && pos + mem_length <= offset + length {
+            // Offsets computed by the analysis wrap around, like the EVM arithmetic
+            let (end, overflow) = pos.overflowing_add(mem_length);
+            if overflow || pos > U256::from(usize::MAX) {
+                continue;
+            }
+            let overwritten = written.iter().any(|(s, e)| pos < *e && *s < end);
+            written.push((pos, end));
+            if overwritten || mem_length != U256::from(32) {
+                continue;
+            }
+            if pos >= offset && end <= offset.saturating_add(length) {
                 vector.push((pos.as_usize(), el.1.clone()));
             }
         }
@@ -1,7 +1,87 @@
+use crate::contract_utils::keccak256;
 use crate::evm_memory::{EvmMemory, EvmStack};
-use ethereum_types::U256;
+use ethereum_types::{U256, U512};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
 
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+/// The instruction used to invoke an external contract
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CallKind {
+    /// `CALL`: runs the callee code on the callee storage
+    Call,
+    /// `CALLCODE`: runs the callee code on the caller storage
+    CallCode,
+    /// `DELEGATECALL`: runs the callee code on the caller storage, keeping caller and value
+    DelegateCall,
+    /// `STATICCALL`: runs the callee code on the callee storage, without modifying it
+    StaticCall,
+}
+
+impl CallKind {
+    /// Whether the callee code accesses the storage of the caller
+    pub fn is_delegated(&self) -> bool {
+        match self {
+            CallKind::CallCode | CallKind::DelegateCall => true,
+            CallKind::Call | CallKind::StaticCall => false,
+        }
+    }
+    /// Whether the callee is forbidden from modifying the state
+    pub fn is_read_only(&self) -> bool {
+        *self == CallKind::StaticCall
+    }
+}
+
+/// A contract deployment performed during the execution of a method
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ContractCreation {
+    /// `CREATE` with the given init code; the address depends on the nonce of the deployer
+    Create(StackValue),
+    /// `CREATE2` with the given init code and salt
+    Create2(StackValue, StackValue),
+}
+
+impl ContractCreation {
+    /// Computes the address of the deployed contract
+    ///
+    /// This is only possible for `CREATE2`, when both the init code and the salt are known.
+    pub fn address(&self, deployer: U256) -> Option<U256> {
+        match self {
+            ContractCreation::Create2(StackValue::CodeSection(code), salt) => {
+                let salt = salt.resolve()?;
+                let mut word = [0u8; 32];
+                let mut buffer = Vec::with_capacity(85);
+                buffer.push(0xff);
+                deployer.to_big_endian(&mut word);
+                buffer.extend_from_slice(&word[12..]);
+                salt.to_big_endian(&mut word);
+                buffer.extend_from_slice(&word);
+                buffer.extend_from_slice(&keccak256(code));
+                let hash = keccak256(&buffer);
+                Some(U256::from(&hash[12..]))
+            }
+            _ => None,
+        }
+    }
+
+    /// Replace PlaceHolders with actual values
+    pub fn replace_parent_call(
+        &self,
+        extended_stack: &EvmStack,
+        extended_memory: &EvmMemory,
+    ) -> ContractCreation {
+        match self {
+            ContractCreation::Create(code) => {
+                ContractCreation::Create(code.replace_parent_call(extended_stack, extended_memory))
+            }
+            ContractCreation::Create2(code, salt) => ContractCreation::Create2(
+                code.replace_parent_call(extended_stack, extended_memory),
+                salt.replace_parent_call(extended_stack, extended_memory),
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum StackValue {
     ActualValue(U256),
     Calldata,
@@ -31,7 +111,8 @@ pub enum StackValue {
     ShL(Box<StackValue>, Box<StackValue>),
     Shr(Box<StackValue>, Box<StackValue>),
     Sar(Box<StackValue>, Box<StackValue>),
-    Sha3(Vec<(usize, StackValue)>),
+    /// Hash of the memory area at (offset, length), with the 32 bytes words stored in it
+    Sha3(U256, U256, Vec<(usize, StackValue)>),
     Address,
     Balance(Box<StackValue>),
     Origin,
@@ -110,51 +191,132 @@ pub enum StackValue {
     Z3Number(Box<StackValue>, Box<StackValue>),
 }
 
+/// The concrete values of the context a method is executed in, as seen by `StackValue::evaluate`
+///
+/// Values left to None are not known, so the expressions depending on them cannot be evaluated.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConcreteContext {
+    /// The account whose code is executed (`ADDRESS`)
+    pub address: Option<U256>,
+    /// The account invoking the method (`CALLER`)
+    pub caller: Option<U256>,
+    /// The account sending the transaction (`ORIGIN`)
+    pub origin: Option<U256>,
+    /// The amount of wei sent to the method (`CALLVALUE`)
+    pub value: Option<U256>,
+    /// The calldata of the method, selector included
+    pub calldata: Option<Vec<u8>>,
+    /// The number of the block (`NUMBER`)
+    pub number: Option<U256>,
+    /// The timestamp of the block (`TIMESTAMP`)
+    pub timestamp: Option<U256>,
+    /// The beneficiary of the block (`COINBASE`)
+    pub coinbase: Option<U256>,
+}
+
 impl StackValue {
+    /// Computes the value of the expression, if it only depends on constants
+    ///
+    /// Operators follow the semantics of the EVM, see `evaluate`.
     pub fn resolve(&self) -> Option<U256> {
+        self.evaluate(&ConcreteContext::default())
+    }
+
+    /// Computes the value of the expression with the values of `context`
+    ///
+    /// Unlike `resolve`, the expression can depend on the transaction (e.g. the key of a mapping
+    /// computed from `CALLER` or from the calldata). Returns None when the expression uses a value
+    /// that is not in the context.
+    ///
+    /// Operators follow the semantics of the EVM: arithmetic wraps around at 2^256, divisions by
+    /// zero give zero and signed operators use the two's complement. `Sha3` is hashed when its
+    /// words exactly cover the hashed area.
+    pub fn evaluate(&self, context: &ConcreteContext) -> Option<U256> {
+        let unary = |a: &StackValue, op: fn(U256) -> U256| a.evaluate(context).map(op);
+        let binary = |a: &StackValue, b: &StackValue, op: fn(U256, U256) -> U256| {
+            Some(op(a.evaluate(context)?, b.evaluate(context)?))
+        };
         match self {
             StackValue::ActualValue(x) => Some(*x),
-            StackValue::Add(a, b) => {
-                if let Some(x) = a.resolve() {
-                    if let Some(y) = b.resolve() {
-                        return Some(x + y);
-                    }
+            // A section longer than a word is read like MLOAD, from its first 32 bytes
+            StackValue::CodeSection(x) => Some(U256::from(&x[..usize::min(x.len(), 32)])),
+            StackValue::Address => context.address,
+            StackValue::Caller => context.caller,
+            StackValue::Origin => context.origin,
+            StackValue::CallValue => context.value,
+            StackValue::Number => context.number,
+            StackValue::TimeStamp => context.timestamp,
+            StackValue::CoinBase => context.coinbase,
+            StackValue::CallDataSize => context.calldata.as_ref().map(|c| U256::from(c.len())),
+            StackValue::CallDataLoad(offset) => {
+                let calldata = context.calldata.as_ref()?;
+                let offset = offset.evaluate(context)?;
+                // Bytes past the end of the calldata are zero
+                let mut word = [0u8; 32];
+                if offset < U256::from(calldata.len()) {
+                    let offset = offset.as_usize();
+                    let end = usize::min(offset + 32, calldata.len());
+                    word[..end - offset].copy_from_slice(&calldata[offset..end]);
                 }
-                None
+                Some(U256::from(&word[..]))
             }
-            StackValue::CodeSection(x) => Some(U256::from(&x[..])),
-            StackValue::ShL(a, b) => {
-                if let Some(x) = b.resolve() {
-                    if let Some(y) = a.resolve() {
-                        return Some(x << y);
+            StackValue::Sha3(offset, length, words) => {
+                // The stored words must cover the hashed area, without gaps or overlaps
+                let mut sorted: Vec<&(usize, StackValue)> = words.iter().collect();
+                sorted.sort_by_key(|w| w.0);
+                let mut data = Vec::new();
+                let mut end = *offset;
+                for (position, word) in sorted {
+                    if U256::from(*position) != end {
+                        return None;
                     }
+                    let mut bytes = [0u8; 32];
+                    word.evaluate(context)?.to_big_endian(&mut bytes);
+                    data.extend_from_slice(&bytes);
+                    end = end.checked_add(U256::from(32))?;
                 }
-                None
-            }
-            StackValue::Shr(a, b) => {
-                if let Some(x) = b.resolve() {
-                    if let Some(y) = a.resolve() {
-                        return Some(x >> y);
-                    }
+                if offset.checked_add(*length)? != end {
+                    return None;
                 }
-                None
+                Some(U256::from(&keccak256(&data)[..]))
             }
-            StackValue::And(a, b) => {
-                if let Some(x) = b.resolve() {
-                    if let Some(y) = a.resolve() {
-                        return Some(x & y);
-                    }
-                }
-                None
+            StackValue::Add(a, b) => binary(a, b, |x, y| x.overflowing_add(y).0),
+            StackValue::Sub(a, b) => binary(a, b, |x, y| x.overflowing_sub(y).0),
+            StackValue::Mul(a, b) => binary(a, b, |x, y| x.overflowing_mul(y).0),
+            StackValue::Div(a, b) => binary(a, b, |x, y| x.checked_div(y).unwrap_or_default()),
+            StackValue::SDiv(a, b) => binary(a, b, signed_div),
+            StackValue::Mod(a, b) => binary(a, b, |x, y| x.checked_rem(y).unwrap_or_default()),
+            StackValue::SMod(a, b) => binary(a, b, signed_mod),
+            StackValue::AddMod(a, b, n) => {
+                let (x, y) = (a.evaluate(context)?, b.evaluate(context)?);
+                Some(add_mod(x, y, n.evaluate(context)?))
             }
-            StackValue::Or(a, b) => {
-                if let Some(x) = b.resolve() {
-                    if let Some(y) = a.resolve() {
-                        return Some(x | y);
-                    }
-                }
-                None
+            StackValue::MulMod(a, b, n) => {
+                let (x, y) = (a.evaluate(context)?, b.evaluate(context)?);
+                Some(mul_mod(x, y, n.evaluate(context)?))
             }
+            StackValue::Exp(a, b) => binary(a, b, |x, y| x.overflowing_pow(y).0),
+            StackValue::SignExtend(a, b) => binary(a, b, sign_extend),
+            StackValue::LT(a, b) => binary(a, b, |x, y| boolean(x < y)),
+            StackValue::GT(a, b) => binary(a, b, |x, y| boolean(x > y)),
+            StackValue::SLT(a, b) => binary(a, b, |x, y| boolean(signed_lt(x, y))),
+            StackValue::SGT(a, b) => binary(a, b, |x, y| boolean(signed_lt(y, x))),
+            StackValue::EQ(a, b) => binary(a, b, |x, y| boolean(x == y)),
+            StackValue::IsZero(a) => unary(a, |x| boolean(x.is_zero())),
+            StackValue::And(a, b) => binary(a, b, |x, y| x & y),
+            StackValue::Or(a, b) => binary(a, b, |x, y| x | y),
+            StackValue::Xor(a, b) => binary(a, b, |x, y| x ^ y),
+            StackValue::Not(a) => unary(a, |x| !x),
+            StackValue::Byte(a, b) => binary(a, b, byte),
+            StackValue::ShL(a, b) => binary(a, b, |shift, x| match shift {
+                _ if shift >= U256::from(256) => U256::zero(),
+                _ => x << shift.as_usize(),
+            }),
+            StackValue::Shr(a, b) => binary(a, b, |shift, x| match shift {
+                _ if shift >= U256::from(256) => U256::zero(),
+                _ => x >> shift.as_usize(),
+            }),
+            StackValue::Sar(a, b) => binary(a, b, arithmetic_shift_right),
             _ => None,
         }
     }
@@ -167,53 +329,314 @@ impl StackValue {
     ) -> StackValue {
         match self {
             StackValue::StackPaceHolder(a) => extended_stack.clone_pos(*a),
-            StackValue::MemoryPlaceHolder(a, b) => {
-                if let Some(v) = extended_memory.retrive(
-                    a.replace_parent_call(extended_stack, extended_memory),
-                    b.replace_parent_call(extended_stack, extended_memory),
-                ) {
-                    v
-                } else {
-                    StackValue::MemoryPlaceHolder(
-                        Box::from(a.replace_parent_call(extended_stack, extended_memory)),
-                        Box::from(b.replace_parent_call(extended_stack, extended_memory)),
-                    )
-                }
-            }
-            StackValue::Add(a, b) => StackValue::Add(
-                Box::from(a.replace_parent_call(extended_stack, extended_memory)),
-                Box::from(b.replace_parent_call(extended_stack, extended_memory)),
+            StackValue::MemoryPlaceHolder(a, b) => extended_memory.retrive(
+                a.replace_parent_call(extended_stack, extended_memory),
+                b.replace_parent_call(extended_stack, extended_memory),
             ),
-            StackValue::Mul(a, b) => StackValue::Mul(
-                Box::from(a.replace_parent_call(extended_stack, extended_memory)),
-                Box::from(b.replace_parent_call(extended_stack, extended_memory)),
+            _ => self.map_children(|v| v.replace_parent_call(extended_stack, extended_memory)),
+        }
+    }
+
+    /// The operands of the expression, in order
+    pub fn children(&self) -> Vec<&StackValue> {
+        use StackValue::*;
+        match self {
+            ActualValue(_) | Calldata | Unknown | Address | Origin | Caller | CallValue
+            | CallDataSize | CodeSize | GasPrice | ReturnDataSize | CoinBase | TimeStamp
+            | Number | Difficulty | GasLimit | PC | MSize | Gas | CodeSection(_)
+            | StackPaceHolder(_) => Vec::new(),
+            IsZero(a) | Not(a) | Balance(a) | CallDataLoad(a) | ExtCodeSize(a) | ExtCodeHash(a)
+            | Blockhash(a) | SLoad(a) => vec![a],
+            Add(a, b)
+            | Mul(a, b)
+            | Sub(a, b)
+            | Div(a, b)
+            | SDiv(a, b)
+            | Mod(a, b)
+            | SMod(a, b)
+            | Exp(a, b)
+            | SignExtend(a, b)
+            | LT(a, b)
+            | GT(a, b)
+            | SLT(a, b)
+            | SGT(a, b)
+            | EQ(a, b)
+            | And(a, b)
+            | Or(a, b)
+            | Xor(a, b)
+            | Byte(a, b)
+            | ShL(a, b)
+            | Shr(a, b)
+            | Sar(a, b)
+            | CalldataCopy(a, b)
+            | CodeCopy(a, b)
+            | ReturnDataCopy(a, b)
+            | MemoryPlaceHolder(a, b)
+            | Z3Number(a, b) => vec![a, b],
+            AddMod(a, b, c)
+            | MulMod(a, b, c)
+            | ExtCodeCopy(a, b, c)
+            | Create(a, b, c)
+            | Extract(a, b, c) => vec![a, b, c],
+            Create2(a, b, c, d) => vec![a, b, c, d],
+            DelegateCall(a, b, c, d, e, f) | StaticCall(a, b, c, d, e, f) => {
+                vec![a, b, c, d, e, f]
+            }
+            Call(a, b, c, d, e, f, g) | CallCode(a, b, c, d, e, f, g) => {
+                vec![a, b, c, d, e, f, g]
+            }
+            Sha3(_, _, words) => words.iter().map(|w| &w.1).collect(),
+            Concat(values) => values.iter().collect(),
+        }
+    }
+
+    /// Rebuilds the expression with the operands replaced by `f`, in order
+    ///
+    /// Values without operands are cloned; the hashed area of `Sha3` and the offsets of its
+    /// words are kept.
+    pub fn map_children(&self, mut f: impl FnMut(&StackValue) -> StackValue) -> StackValue {
+        use StackValue::*;
+        let mut m = |v: &StackValue| Box::from(f(v));
+        match self {
+            ActualValue(_) | Calldata | Unknown | Address | Origin | Caller | CallValue
+            | CallDataSize | CodeSize | GasPrice | ReturnDataSize | CoinBase | TimeStamp
+            | Number | Difficulty | GasLimit | PC | MSize | Gas | CodeSection(_)
+            | StackPaceHolder(_) => self.clone(),
+            IsZero(a) => IsZero(m(a)),
+            Not(a) => Not(m(a)),
+            Balance(a) => Balance(m(a)),
+            CallDataLoad(a) => CallDataLoad(m(a)),
+            ExtCodeSize(a) => ExtCodeSize(m(a)),
+            ExtCodeHash(a) => ExtCodeHash(m(a)),
+            Blockhash(a) => Blockhash(m(a)),
+            SLoad(a) => SLoad(m(a)),
+            Add(a, b) => Add(m(a), m(b)),
+            Mul(a, b) => Mul(m(a), m(b)),
+            Sub(a, b) => Sub(m(a), m(b)),
+            Div(a, b) => Div(m(a), m(b)),
+            SDiv(a, b) => SDiv(m(a), m(b)),
+            Mod(a, b) => Mod(m(a), m(b)),
+            SMod(a, b) => SMod(m(a), m(b)),
+            Exp(a, b) => Exp(m(a), m(b)),
+            SignExtend(a, b) => SignExtend(m(a), m(b)),
+            LT(a, b) => LT(m(a), m(b)),
+            GT(a, b) => GT(m(a), m(b)),
+            SLT(a, b) => SLT(m(a), m(b)),
+            SGT(a, b) => SGT(m(a), m(b)),
+            EQ(a, b) => EQ(m(a), m(b)),
+            And(a, b) => And(m(a), m(b)),
+            Or(a, b) => Or(m(a), m(b)),
+            Xor(a, b) => Xor(m(a), m(b)),
+            Byte(a, b) => Byte(m(a), m(b)),
+            ShL(a, b) => ShL(m(a), m(b)),
+            Shr(a, b) => Shr(m(a), m(b)),
+            Sar(a, b) => Sar(m(a), m(b)),
+            CalldataCopy(a, b) => CalldataCopy(m(a), m(b)),
+            CodeCopy(a, b) => CodeCopy(m(a), m(b)),
+            ReturnDataCopy(a, b) => ReturnDataCopy(m(a), m(b)),
+            MemoryPlaceHolder(a, b) => MemoryPlaceHolder(m(a), m(b)),
+            Z3Number(a, b) => Z3Number(m(a), m(b)),
+            AddMod(a, b, c) => AddMod(m(a), m(b), m(c)),
+            MulMod(a, b, c) => MulMod(m(a), m(b), m(c)),
+            ExtCodeCopy(a, b, c) => ExtCodeCopy(m(a), m(b), m(c)),
+            Create(a, b, c) => Create(m(a), m(b), m(c)),
+            Extract(a, b, c) => Extract(m(a), m(b), m(c)),
+            Create2(a, b, c, d) => Create2(m(a), m(b), m(c), m(d)),
+            DelegateCall(a, b, c, d, e, g) => DelegateCall(m(a), m(b), m(c), m(d), m(e), m(g)),
+            StaticCall(a, b, c, d, e, g) => StaticCall(m(a), m(b), m(c), m(d), m(e), m(g)),
+            Call(a, b, c, d, e, g, h) => Call(m(a), m(b), m(c), m(d), m(e), m(g), m(h)),
+            CallCode(a, b, c, d, e, g, h) => CallCode(m(a), m(b), m(c), m(d), m(e), m(g), m(h)),
+            Sha3(offset, length, words) => Sha3(
+                *offset,
+                *length,
+                words.iter().map(|(o, v)| (*o, *m(v))).collect(),
             ),
-            StackValue::Sha3(v) => {
-                let mut v2 = Vec::new();
-                for item in v {
-                    v2.push((
-                        item.0,
-                        item.1.replace_parent_call(extended_stack, extended_memory),
-                    ));
+            Concat(values) => Concat(values.iter().map(|v| *m(v)).collect()),
+        }
+    }
+
+    /// Visits the expression in depth-first order, see `StackValueVisitor`
+    pub fn walk<'a>(&'a self, visitor: &mut impl StackValueVisitor<'a>) {
+        if visitor.visit(self) {
+            for child in self.children() {
+                child.walk(visitor);
+            }
+        }
+        visitor.leave(self);
+    }
+
+    /// Computes a value bottom-up: `f` is called on every node with the values of its operands
+    pub fn fold<T>(&self, f: &mut impl FnMut(&StackValue, Vec<T>) -> T) -> T {
+        let children = self.children().into_iter().map(|c| c.fold(f)).collect();
+        f(self, children)
+    }
+
+    /// Whether the expression, or one of its operands at any depth, satisfies `predicate`
+    pub fn contains(&self, predicate: &impl Fn(&StackValue) -> bool) -> bool {
+        predicate(self) || self.children().iter().any(|c| c.contains(predicate))
+    }
+
+    /// Number of nodes on the longest path from the expression to a value without operands
+    pub fn depth(&self) -> usize {
+        self.fold(&mut |_, children: Vec<usize>| 1 + children.into_iter().max().unwrap_or(0))
+    }
+
+    /// Number of nodes of the expression
+    pub fn size(&self) -> usize {
+        self.fold(&mut |_, children: Vec<usize>| 1 + children.into_iter().sum::<usize>())
+    }
+
+    /// The values read from the calldata, the storage or the caller (`CallDataLoad`, `SLoad` and
+    /// the placeholders), without duplicates and in the order they are visited
+    pub fn free_variables(&self) -> Vec<&StackValue> {
+        let mut visitor = FreeVariables(Vec::new());
+        self.walk(&mut visitor);
+        visitor.0
+    }
+}
+
+/// An analysis of `StackValue` trees, run by `StackValue::walk`
+///
+/// Implementations only handle the variants they are interested in, the traversal reaches the
+/// operands of every variant.
+pub trait StackValueVisitor<'a> {
+    /// Called on every node before its operands; returning false skips the operands
+    fn visit(&mut self, value: &'a StackValue) -> bool;
+    /// Called on every node after its operands
+    fn leave(&mut self, _value: &'a StackValue) {}
+}
+
+struct FreeVariables<'a>(Vec<&'a StackValue>);
+
+impl<'a> StackValueVisitor<'a> for FreeVariables<'a> {
+    fn visit(&mut self, value: &'a StackValue) -> bool {
+        match value {
+            StackValue::CallDataLoad(_)
+            | StackValue::SLoad(_)
+            | StackValue::StackPaceHolder(_)
+            | StackValue::MemoryPlaceHolder(_, _) => {
+                if !self.0.contains(&value) {
+                    self.0.push(value);
                 }
-                StackValue::Sha3(v2)
             }
-            StackValue::CallDataLoad(v) => StackValue::CallDataLoad(Box::from(
-                v.replace_parent_call(extended_stack, extended_memory),
-            )),
-            StackValue::EQ(a, b) => StackValue::EQ(
-                Box::from(a.replace_parent_call(extended_stack, extended_memory)),
-                Box::from(b.replace_parent_call(extended_stack, extended_memory)),
-            ),
-            StackValue::And(a, b) => StackValue::And(
-                Box::from(a.replace_parent_call(extended_stack, extended_memory)),
-                Box::from(b.replace_parent_call(extended_stack, extended_memory)),
-            ),
-            StackValue::ShL(a, b) => StackValue::ShL(
-                Box::from(a.replace_parent_call(extended_stack, extended_memory)),
-                Box::from(b.replace_parent_call(extended_stack, extended_memory)),
-            ),
-            _ => self.clone(),
+            _ => {}
         }
+        true
+    }
+}
+
+/// The EVM representation of a boolean
+fn boolean(value: bool) -> U256 {
+    if value {
+        U256::one()
+    } else {
+        U256::zero()
+    }
+}
+
+/// Whether a word is negative, when read as a two's complement signed integer
+fn is_negative(x: U256) -> bool {
+    x.bit(255)
+}
+
+/// The two's complement opposite of a word
+fn negate(x: U256) -> U256 {
+    (!x).overflowing_add(U256::one()).0
+}
+
+fn absolute(x: U256) -> U256 {
+    if is_negative(x) {
+        negate(x)
+    } else {
+        x
+    }
+}
+
+/// `SDIV`: the quotient is rounded towards zero
+fn signed_div(x: U256, y: U256) -> U256 {
+    if y.is_zero() {
+        return U256::zero();
+    }
+    let quotient = absolute(x) / absolute(y);
+    if is_negative(x) != is_negative(y) {
+        negate(quotient)
+    } else {
+        quotient
+    }
+}
+
+/// `SMOD`: the remainder has the sign of the dividend
+fn signed_mod(x: U256, y: U256) -> U256 {
+    if y.is_zero() {
+        return U256::zero();
+    }
+    let remainder = absolute(x) % absolute(y);
+    if is_negative(x) {
+        negate(remainder)
+    } else {
+        remainder
+    }
+}
+
+fn signed_lt(x: U256, y: U256) -> bool {
+    match (is_negative(x), is_negative(y)) {
+        (true, false) => true,
+        (false, true) => false,
+        _ => x < y,
+    }
+}
+
+/// `ADDMOD`: the sum is not truncated to 256 bits
+fn add_mod(x: U256, y: U256, n: U256) -> U256 {
+    if n.is_zero() {
+        return U256::zero();
+    }
+    let sum = U512::from(x) + U512::from(y);
+    U256::try_from(sum % U512::from(n)).unwrap()
+}
+
+/// `MULMOD`: the product is not truncated to 256 bits
+fn mul_mod(x: U256, y: U256, n: U256) -> U256 {
+    if n.is_zero() {
+        return U256::zero();
+    }
+    U256::try_from(x.full_mul(y) % U512::from(n)).unwrap()
+}
+
+/// `SIGNEXTEND`: extends the sign of the lowest `b + 1` bytes of `x`
+fn sign_extend(b: U256, x: U256) -> U256 {
+    if b >= U256::from(31) {
+        return x;
+    }
+    let sign_bit = b.as_usize() * 8 + 7;
+    let mask = (U256::one() << (sign_bit + 1)) - U256::one();
+    if x.bit(sign_bit) {
+        x | !mask
+    } else {
+        x & mask
+    }
+}
+
+/// `BYTE`: the `i`-th byte of `x`, starting from the most significant one
+fn byte(i: U256, x: U256) -> U256 {
+    if i >= U256::from(32) {
+        return U256::zero();
+    }
+    U256::from(x.byte(31 - i.as_usize()))
+}
+
+/// `SAR`: the vacated bits are filled with the sign bit of `x`
+fn arithmetic_shift_right(shift: U256, x: U256) -> U256 {
+    let fill = match is_negative(x) {
+        true => !U256::zero(),
+        false => U256::zero(),
+    };
+    if shift >= U256::from(256) {
+        return fill;
+    }
+    let shift = shift.as_usize();
+    if shift == 0 {
+        return x;
     }
+    (x >> shift) | (fill << (256 - shift))
 }
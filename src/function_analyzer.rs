@@ -1,50 +1,252 @@
-use crate::contract_logger::{ContractLogger,NoLogger};
+use crate::bytecode::push_size;
+use crate::contract_logger::{ConcurrentLogger, ContractLogger};
 use crate::evm_function::{EvmFunction, FunctionRegistry};
+use crate::evm_types::StackValue;
 use scoped_threadpool::Pool;
-use std::sync::{Arc, Mutex};
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex, OnceLock};
 pub type FunctionAnalyzer<T: ContractLogger> =
     for<'a> fn(code: &'a [u8], functions: &[usize], logger: &mut T) -> FunctionRegistry<'a>;
 
+/// Builds the `FunctionRegistry` of a bytecode, given the starting positions of its blocks
+///
+/// Implemented by every `FunctionAnalyzer` and by `AnalyzerConfig`.
+pub trait BlockAnalyzer<L> {
+    fn analyze<'a>(
+        &self,
+        code: &'a [u8],
+        functions: &[usize],
+        logger: &mut L,
+    ) -> FunctionRegistry<'a>;
+}
+
+impl<L> BlockAnalyzer<L> for FunctionAnalyzer<L> {
+    fn analyze<'a>(
+        &self,
+        code: &'a [u8],
+        functions: &[usize],
+        logger: &mut L,
+    ) -> FunctionRegistry<'a> {
+        self(code, functions, logger)
+    }
+}
+
+/// Code size, in bytes, below which `AnalyzerConfig` analyzes the blocks on the current thread
+pub const SINGLE_THREAD_BELOW: usize = 4096;
+
+/// Number of threads of the pool of the default `AnalyzerConfig`
+pub const DEFAULT_WORKERS: u32 = 4;
+
+/// Analyzes the reachable blocks of the contracts on a thread pool, reused across contracts
+///
+/// Small contracts are analyzed on the current thread, as dispatching their blocks to the pool
+/// costs more than it saves (see `concurrent_benchmark.txt`). The pool is locked while a batch of
+/// blocks is analyzed, so analyses sharing it take turns between batches; it must not be locked
+/// by the caller at the same time.
+#[derive(Clone)]
+pub struct AnalyzerConfig {
+    pool: Arc<Mutex<Pool>>,
+    /// Contracts whose code is shorter than this are analyzed on the current thread
+    pub single_thread_below: usize,
+}
+
+impl AnalyzerConfig {
+    /// Creates a pool of `workers` threads
+    pub fn new(workers: u32) -> Self {
+        AnalyzerConfig::with_pool(Arc::from(Mutex::from(Pool::new(workers))))
+    }
+
+    /// Uses a pool owned by the caller, which can also be passed to `execute_net_with_pool`
+    pub fn with_pool(pool: Arc<Mutex<Pool>>) -> Self {
+        AnalyzerConfig {
+            pool,
+            single_thread_below: SINGLE_THREAD_BELOW,
+        }
+    }
+
+    /// The pool analyzing the blocks
+    pub fn pool(&self) -> Arc<Mutex<Pool>> {
+        self.pool.clone()
+    }
+}
+
+/// The configuration used by `analyze_contract_default` and `NetBuilder::register_code`
+///
+/// Every default config shares one pool of `DEFAULT_WORKERS` threads, created on first use.
+impl Default for AnalyzerConfig {
+    fn default() -> Self {
+        static SHARED: OnceLock<AnalyzerConfig> = OnceLock::new();
+        SHARED
+            .get_or_init(|| AnalyzerConfig::new(DEFAULT_WORKERS))
+            .clone()
+    }
+}
+
+impl<L: ConcurrentLogger> BlockAnalyzer<L> for AnalyzerConfig {
+    fn analyze<'a>(
+        &self,
+        code: &'a [u8],
+        functions: &[usize],
+        logger: &mut L,
+    ) -> FunctionRegistry<'a> {
+        if code.len() < self.single_thread_below {
+            return single_threded_function_analyzer(code, functions, logger);
+        }
+        analyze_in_pool(code, functions, &self.pool, logger)
+    }
+}
+
+/// Number of blocks analyzed and skipped by a `FunctionAnalyzer`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AnalysisStats {
+    /// Blocks found in the bytecode by `list_functions`
+    pub listed: usize,
+    /// Blocks executed so far
+    pub analyzed: usize,
+    /// Blocks never executed, e.g. because they cannot be reached
+    pub skipped: usize,
+}
+
 pub fn single_threded_function_analyzer<'a>(
     code: &'a [u8],
     functions: &[usize],
     logger: &mut impl ContractLogger,
 ) -> FunctionRegistry<'a> {
-    let mut registry = FunctionRegistry::new();
-    for f_loc in functions {
-        logger.log_new_function(*f_loc);
-        let mut evm_func = EvmFunction::new(*f_loc, code);
-        evm_func.execute(logger);
-        logger.finalize_function(&evm_func);
-        registry.analyzed.insert(*f_loc, evm_func);
-        
-    }
-    registry
+    analyze_reachable(code, functions, |batch, registry| {
+        for f_loc in batch {
+            registry.analyze(*f_loc, logger);
+        }
+    })
 }
 
+/// Analyzes the reachable blocks on the pool of the default `AnalyzerConfig`, whatever the size
+/// of the code
 pub fn multi_threded_function_analyzer<'a>(
+    code: &'a [u8],
+    functions: &[usize],
+    logger: &mut impl ConcurrentLogger,
+) -> FunctionRegistry<'a> {
+    analyze_in_pool(code, functions, &AnalyzerConfig::default().pool, logger)
+}
+
+/// Analyzes the reachable blocks, executing each batch on the threads of `pool`
+///
+/// Every block is logged by its own child of `logger`, merged back in the order of the batch. The
+/// pool is only locked while a batch runs.
+fn analyze_in_pool<'a, L: ConcurrentLogger>(
+    code: &'a [u8],
+    functions: &[usize],
+    pool: &Mutex<Pool>,
+    logger: &mut L,
+) -> FunctionRegistry<'a> {
+    analyze_reachable(code, functions, |batch, registry| {
+        let mut children: Vec<L::Child> = batch.iter().map(|_| logger.child()).collect();
+        pool.lock().unwrap().scoped(|scope| {
+            for (f_loc, child) in batch.iter().zip(children.iter_mut()) {
+                scope.execute(move || {
+                    registry.analyze(*f_loc, child);
+                });
+            }
+        });
+        for child in children {
+            logger.merge(child);
+        }
+    })
+}
+
+/// Does not analyze any block in advance: blocks are analyzed when first requested to the registry
+///
+/// Only the blocks actually reached while resolving the methods of the contract are executed; the
+/// analysis functions of `contract_analyzer` log them with the logger they were given.
+pub fn lazy_function_analyzer<'a>(
     code: &'a [u8],
     functions: &[usize],
     _logger: &mut impl ContractLogger,
 ) -> FunctionRegistry<'a> {
-    let mut registry = FunctionRegistry::new();
-    let mutexp = Arc::from(Mutex::from(&mut registry));
-    let ocode: Arc<&[u8]> = Arc::from(code);
-    let n_workers = 4;
-    let mut pool = Pool::new(n_workers);
-    pool.scoped(|scope| {
-        for f_loc in functions {
-            let cloned = *f_loc;
-            let owned_code = ocode.clone();
-            let mutex = mutexp.clone();
-            scope.execute(move || {
-                let code = owned_code;
-                let mut evm_func = EvmFunction::new(cloned, &code[..]);
-                evm_func.execute(&mut NoLogger());
-                let lock = mutex.lock();
-                lock.unwrap().analyzed.insert(cloned, evm_func.clone());
-            });
-        }
-    });
-    return registry;
+    FunctionRegistry::new(code, functions)
+}
+
+/// Analyzes the blocks in `functions` that can be reached from the start of the code
+///
+/// Blocks are analyzed in batches by `analyze`: the first batch only contains the start of the
+/// code, the next ones the blocks reachable from the previous batches. The blocks never reached are
+/// left to be analyzed on demand, see `FunctionRegistry::skipped`.
+fn analyze_reachable<'a>(
+    code: &'a [u8],
+    functions: &[usize],
+    mut analyze: impl FnMut(&[usize], &FunctionRegistry<'a>),
+) -> FunctionRegistry<'a> {
+    let listed: HashSet<usize> = functions.iter().cloned().collect();
+    let registry = FunctionRegistry::new(code, functions);
+    let mut batch = if listed.contains(&0) {
+        vec![0]
+    } else {
+        Vec::new()
+    };
+    let mut queued: HashSet<usize> = batch.iter().cloned().collect();
+    while !batch.is_empty() {
+        analyze(&batch, &registry);
+        let mut next = Vec::new();
+        for f_loc in &batch {
+            let targets = match registry.get_from_address(*f_loc).and_then(successors) {
+                Some(targets) => targets,
+                // Jump targets might be computed: every block might be reachable
+                None => functions.to_vec(),
+            };
+            for target in targets {
+                if listed.contains(&target) && queued.insert(target) {
+                    next.push(target);
+                }
+            }
+        }
+        next.sort();
+        batch = next;
+    }
+    registry
+}
+
+/// Blocks that might be executed after `function`
+///
+/// Besides the resolved targets of its jumps, every `JUMPDEST` pushed by the block is included,
+/// together with the constants it leaves on the stack and in memory for the blocks it calls (e.g. a
+/// return address computed as `PUSH a PUSH b SUB`), since they might be used as jump targets by
+/// them. Returns None when the target of a jump is neither a constant nor a value taken from the
+/// caller, as it might have been computed.
+fn successors(function: &EvmFunction) -> Option<Vec<usize>> {
+    let mut targets = Vec::new();
+    let mut push_target = |value: &StackValue| {
+        if let Some(target) = value.resolve() {
+            if target < usize::MAX.into() {
+                targets.push(target.as_usize());
+            }
+        }
+    };
+    for call in &function.internal_calls {
+        match call.0 {
+            StackValue::StackPaceHolder(_) | StackValue::MemoryPlaceHolder(_, _) => {}
+            _ if call.0.resolve().is_some() => push_target(&call.0),
+            _ => return None,
+        }
+        for value in &call.1.stack {
+            push_target(value);
+        }
+        for entry in call.2.entries() {
+            push_target(&entry.1);
+        }
+    }
+    let code = function.code;
+    let end = usize::min(function.pc, code.len());
+    let mut pc = function.position;
+    while pc < end {
+        let size = push_size(code[pc]);
+        let next = usize::min(pc + size + 1, code.len());
+        if size > 0 && size <= 8 {
+            let value = code[pc + 1..next]
+                .iter()
+                .fold(0u64, |acc, byte| (acc << 8) | *byte as u64);
+            targets.push(value as usize);
+        }
+        pc = next;
+    }
+    Some(targets)
 }
@@ -1,10 +1,16 @@
 extern crate ethereum_types;
 
+pub mod abi;
+pub mod analysis_error;
+pub mod bytecode;
+pub mod cache;
+pub mod cfg;
 pub mod contract_analyzer;
 pub mod contract_data;
 pub mod contract_utils;
 //pub mod evm_execution;
 pub mod cycle_resolution;
+pub mod dispatcher;
 pub mod evm_function;
 pub mod evm_instructions;
 pub mod evm_memory;
@@ -14,10 +20,12 @@ pub mod contract_logger;
 pub mod expression_simplify_sys;
 pub mod function_analyzer;
 pub mod net;
+pub use analysis_error::AnalysisError;
 pub use contract_analyzer::analyze_contract_default;
 pub use contract_data::ContractData;
-pub use net::net_executor::execute_net;
-pub use net::netbuilder::NetBuilder;
+pub use function_analyzer::AnalyzerConfig;
+pub use net::net_executor::{execute_net, execute_net_with_pool};
+pub use net::netbuilder::{NetBuilder, UnknownCodePolicy};
 pub use net::transaction::{ChainStateProvider, RunningFunction, TransactionDataProvider,MethodType};
 /*macro_rules! u56 {
     (a:$expr) => {
@@ -26,9 +34,36 @@ pub use net::transaction::{ChainStateProvider, RunningFunction, TransactionDataP
 }*/
 #[cfg(test)]
 mod tests {
-    use crate::contract_analyzer::{analyze_contract_default, analyze_contract_single};
+    use crate::abi::{parse_abi, StateMutability};
+    use crate::analysis_error::AnalysisError;
+    use crate::bytecode::{parse_metadata, split_code};
+    use crate::cache::{AnalysisCache, SerializationError};
+    use crate::cfg::Cfg;
+    use crate::contract_analyzer::{
+        analyze_contract, analyze_contract_cached, analyze_contract_default,
+        analyze_contract_single, analyze_runtime_code, analyze_runtime_code_default,
+        extract_runtime_code, list_functions,
+    };
+    use crate::contract_data::{ContractData, ContractMethod};
+    use crate::contract_logger::{ConcurrentLogger, ContractLogger, NoLogger};
+    use crate::contract_utils::{keccak256, DataType};
+    use crate::cycle_resolution::{CycleSolver, MaxIterations, NocycleSolver};
+    use crate::dispatcher::{
+        CalldataDispatcher, DefaultDispatcher, DispatcherRecognizer, DivDispatcher, ShrDispatcher,
+        VyperDispatcher,
+    };
+    use crate::evm_function::EvmFunction;
+    use crate::evm_memory::{EvmMemory, EvmStack};
+    use crate::evm_types::{ConcreteContext, ContractCreation, StackValue, StackValueVisitor};
+    use crate::function_analyzer::{
+        lazy_function_analyzer, multi_threded_function_analyzer, single_threded_function_analyzer,
+        AnalysisStats, AnalyzerConfig, BlockAnalyzer, FunctionAnalyzer,
+    };
+    use ethereum_types::U256;
+    use std::collections::HashSet;
     use std::fs::File;
     use std::io::prelude::*;
+    use std::sync::Arc;
     use std::time::Instant;
 
     fn test_code_s(code: &[u8]) {
@@ -42,7 +77,7 @@ mod tests {
         println!("Size: {} bytes", len);
         for _ in 1..100 {
             let time = Instant::now();
-            analyze_contract_default(code);
+            let _ = analyze_contract_default(code);
             let elapsed = time.elapsed();
             timings.push(elapsed.as_nanos());
         }
@@ -66,7 +101,7 @@ mod tests {
 
         for _ in 1..100 {
             let time = Instant::now();
-            analyze_contract_single(code);
+            let _ = analyze_contract_single(code);
             let elapsed = time.elapsed();
             timings.push(elapsed.as_nanos());
         }
@@ -897,6 +932,19 @@ mod tests {
             0xcd, 0x00, 0x33, 0x27, 0x1a, 0x95, 0x09, 0xf9, 0xa2, 0xb1, 0x2f, 0xc3, 0xd1, 0xd4,
             0x64, 0x73, 0x6f, 0x6c, 0x63, 0x43, 0x00, 0x06, 0x06, 0x00, 0x33,
         ];
+        let data = analyze_contract_default(&code).unwrap();
+        // delegate(address) follows the chain of delegates in a loop, reading and writing
+        // voters[voters[to].delegate]
+        let delegate = &data.methods[&U256::from(0x5c19a95c)];
+        let is_nested = |access: &DataType| match access {
+            DataType::Mapping(base, key) => {
+                *base == StackValue::ActualValue(U256::from(1))
+                    && key.contains(&|v| matches!(v, StackValue::SLoad(_)))
+            }
+            _ => false,
+        };
+        assert!(delegate.storage_read.iter().any(is_nested));
+        assert!(delegate.storage_write.iter().any(is_nested));
         test_code(code)
     }
     /*#[test]
@@ -2665,4 +2713,1153 @@ mod tests {
         buffer.write_all(&code[..]).unwrap();
         test_code(code)
     }
+    #[test]
+    fn truncated_push() {
+        // PUSH1 0x80 PUSH1 0x40 MSTORE PUSH2 0x00
+        let code = vec![0x60, 0x80, 0x60, 0x40, 0x52, 0x61, 0x00];
+        assert_eq!(
+            analyze_contract_default(&code).err(),
+            Some(AnalysisError::TruncatedPush { pc: 5 })
+        );
+    }
+    #[test]
+    fn unresolvable_jump() {
+        // PUSH1 0x00 CALLDATALOAD JUMP
+        let code = vec![0x60, 0x00, 0x35, 0x56];
+        match analyze_contract_default(&code) {
+            Err(AnalysisError::UnresolvableJump { pc, .. }) => assert_eq!(pc, 3),
+            _ => panic!("expected an unresolvable jump"),
+        }
+    }
+    #[test]
+    fn missing_runtime_code() {
+        // PUSH1 0x00 DUP1 RETURN
+        let code = vec![0x60, 0x00, 0x80, 0xf3];
+        assert_eq!(
+            analyze_contract_default(&code).err(),
+            Some(AnalysisError::MissingRuntimeCode { pc: 3 })
+        );
+    }
+    #[test]
+    fn fallback_and_receive() {
+        // Constructor: return the runtime code
+        let mut code = vec![
+            0x60, 0x32, 0x80, 0x60, 0x0b, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3,
+        ];
+        // CALLDATASIZE < 4 => fallback dispatch
+        code.extend(&[0x60, 0x04, 0x36, 0x10, 0x60, 0x17, 0x57]);
+        // Selector 0x11223344
+        code.extend(&[
+            0x60, 0x00, 0x35, 0x60, 0xe0, 0x1c, 0x80, 0x63, 0x11, 0x22, 0x33, 0x44, 0x14, 0x60,
+            0x2b, 0x57,
+        ]);
+        // Fallback dispatch: empty calldata => receive
+        code.extend(&[0x5b, 0x36, 0x15, 0x60, 0x24, 0x57]);
+        // Fallback, receive and method store 1 at slot 3, 2 and 1
+        for (dest, slot) in &[(0x1d, 3), (0x24, 2), (0x2b, 1)] {
+            assert_eq!(code.len() - 11, *dest);
+            code.extend(&[0x5b, 0x60, 0x01, 0x60, *slot as u8, 0x55, 0x00]);
+        }
+        let data = analyze_contract_default(&code).unwrap();
+        let slot = |n: u64| DataType::Field(StackValue::ActualValue(U256::from(n)));
+        let method = &data.methods[&U256::from(0x11223344)];
+        assert_eq!(method.storage_write, vec![slot(1)].into_iter().collect());
+        let fallback = data.fallback.unwrap();
+        assert_eq!(fallback.storage_write, vec![slot(3)].into_iter().collect());
+        let receive = data.receive.unwrap();
+        assert_eq!(receive.storage_write, vec![slot(2)].into_iter().collect());
+    }
+    /// Prepends a constructor returning `runtime` as the code of the contract
+    fn deploy(runtime: &[u8]) -> Vec<u8> {
+        let mut code = vec![0x60, runtime.len() as u8];
+        code.extend_from_slice(&[0x80, 0x60, 0x0b, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3]);
+        code.extend_from_slice(runtime);
+        code
+    }
+    fn fields(slots: &[u64]) -> HashSet<DataType> {
+        slots
+            .iter()
+            .map(|n| DataType::Field(StackValue::ActualValue(U256::from(*n))))
+            .collect()
+    }
+    #[test]
+    fn div_dispatcher() {
+        // Selector 0x11223344 extracted with DIV(CALLDATALOAD(0), 2^224), method writes slot 1
+        let code = deploy(&[
+            0x60, 0x00, 0x35, 0x7c, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x90, 0x04, 0x63, 0xff, 0xff, 0xff, 0xff, 0x16, 0x80,
+            0x63, 0x11, 0x22, 0x33, 0x44, 0x14, 0x60, 0x34, 0x57, 0x00, 0x5b, 0x60, 0x01, 0x60,
+            0x01, 0x55, 0x00,
+        ]);
+        let data = analyze_contract_default(&code).unwrap();
+        assert_eq!(data.methods.len(), 1);
+        assert_eq!(
+            data.methods[&U256::from(0x11223344)].storage_write,
+            fields(&[1])
+        );
+    }
+    #[test]
+    fn vyper_dispatcher() {
+        // MSTORE(28, CALLDATALOAD(0)), the method follows the check ISZERO(EQ(sel, MLOAD(0)))
+        let code = deploy(&[
+            0x60, 0x00, 0x35, 0x60, 0x1c, 0x52, 0x63, 0x11, 0x22, 0x33, 0x44, 0x60, 0x00, 0x51,
+            0x14, 0x15, 0x60, 0x19, 0x57, 0x60, 0x01, 0x60, 0x01, 0x55, 0x00, 0x5b, 0x00,
+        ]);
+        let data = analyze_contract(
+            &code,
+            &NocycleSolver(),
+            &VyperDispatcher(),
+            &(single_threded_function_analyzer as FunctionAnalyzer<NoLogger>),
+            &mut NoLogger(),
+        )
+        .unwrap();
+        assert_eq!(data.methods.len(), 1);
+        assert_eq!(
+            data.methods[&U256::from(0x11223344)].storage_write,
+            fields(&[1])
+        );
+        assert!(data.fallback.unwrap().storage_write.is_empty());
+        // Other compilers extract the selector differently
+        let data = analyze_contract(
+            &code,
+            &NocycleSolver(),
+            &DivDispatcher(),
+            &(single_threded_function_analyzer as FunctionAnalyzer<NoLogger>),
+            &mut NoLogger(),
+        )
+        .unwrap();
+        assert!(data.methods.is_empty());
+    }
+    #[test]
+    fn solc08_dispatcher() {
+        // Storage example of Remix (retrieve and store) compiled by solc 0.8.7, whose dispatcher
+        // reverts for short calldata; the IPFS hash of the metadata is zeroed
+        let code = include_bytes!("../contracts/bytecodes/storage_solc08");
+        let data = analyze_contract_default(code).unwrap();
+        assert_eq!(data.metadata.unwrap().version.as_deref(), Some("0.8.7"));
+        assert_eq!(data.methods.len(), 2);
+        // retrieve()
+        let retrieve = &data.methods[&U256::from(0x2e64cec1)];
+        assert_eq!(retrieve.storage_read, fields(&[0]));
+        assert!(retrieve.storage_write.is_empty());
+        // store(uint256)
+        let store = &data.methods[&U256::from(0x6057361d)];
+        assert_eq!(store.storage_write, fields(&[0]));
+    }
+    #[test]
+    fn vyper_storage_dispatcher() {
+        // Runtime code laid out like Vyper 0.2 output for `x: public(uint256)` and `set(uint256)`:
+        // the selector is stored with MSTORE(28, CALLDATALOAD(0)) and compared with MLOAD(0)
+        let code = include_bytes!("../contracts/bytecodes/vyper_storage");
+        let data = analyze_runtime_code_default(code).unwrap();
+        assert_eq!(data.methods.len(), 2);
+        // set(uint256)
+        let set = &data.methods[&U256::from(0x60fe47b1)];
+        assert_eq!(set.storage_write, fields(&[0]));
+        // x()
+        let x = &data.methods[&U256::from(0x0c55699c)];
+        assert_eq!(x.storage_read, fields(&[0]));
+        assert!(x.storage_write.is_empty());
+    }
+    #[test]
+    fn binary_search_dispatcher() {
+        // selector > 0x50000000 is dispatched in a second block
+        let code = deploy(&[
+            0x60, 0x00, 0x35, 0x60, 0xe0, 0x1c, 0x80, 0x63, 0x50, 0x00, 0x00, 0x00, 0x11, 0x60,
+            0x1b, 0x57, 0x80, 0x63, 0x60, 0x00, 0x00, 0x00, 0x14, 0x60, 0x2e, 0x57, 0x00, 0x5b,
+            0x80, 0x63, 0x40, 0x00, 0x00, 0x00, 0x14, 0x60, 0x27, 0x57, 0x00, 0x5b, 0x60, 0x01,
+            0x60, 0x01, 0x55, 0x00, 0x5b, 0x60, 0x01, 0x60, 0x02, 0x55, 0x00,
+        ]);
+        let data = analyze_contract_default(&code).unwrap();
+        assert_eq!(data.methods.len(), 2);
+        assert_eq!(
+            data.methods[&U256::from(0x40000000)].storage_write,
+            fields(&[1])
+        );
+        assert_eq!(
+            data.methods[&U256::from(0x60000000)].storage_write,
+            fields(&[2])
+        );
+    }
+    #[test]
+    fn huff_dispatcher() {
+        // PUSH0 CALLDATALOAD PUSH1 0xe0 SHR, the method follows the check XOR(sel, selector)
+        let code = deploy(&[
+            0x5f, 0x35, 0x60, 0xe0, 0x1c, 0x80, 0x63, 0x11, 0x22, 0x33, 0x44, 0x18, 0x60, 0x15,
+            0x57, 0x60, 0x01, 0x60, 0x01, 0x55, 0x00, 0x5b, 0x00,
+        ]);
+        let data = analyze_contract_default(&code).unwrap();
+        assert_eq!(data.methods.len(), 1);
+        assert_eq!(
+            data.methods[&U256::from(0x11223344)].storage_write,
+            fields(&[1])
+        );
+    }
+    #[test]
+    fn calldata_dispatcher() {
+        // PUSH0 CALLDATALOAD PUSH1 0xe0 SAR, the method follows the check EQ(sel, selector)
+        let code = deploy(&[
+            0x5f, 0x35, 0x60, 0xe0, 0x1d, 0x80, 0x63, 0x11, 0x22, 0x33, 0x44, 0x14, 0x60, 0x10,
+            0x57, 0x00, 0x5b, 0x60, 0x01, 0x60, 0x01, 0x55, 0x00,
+        ]);
+        let methods = |dispatcher: &dyn DispatcherRecognizer| {
+            analyze_contract(
+                &code,
+                &NocycleSolver(),
+                dispatcher,
+                &(single_threded_function_analyzer as FunctionAnalyzer<NoLogger>),
+                &mut NoLogger(),
+            )
+            .unwrap()
+            .methods
+        };
+        // Only the permissive recognizer, which is not tried by default, accepts it
+        assert!(methods(&ShrDispatcher()).is_empty());
+        assert!(methods(&DefaultDispatcher()).is_empty());
+        let methods = methods(&CalldataDispatcher());
+        assert_eq!(methods.len(), 1);
+        assert_eq!(methods[&U256::from(0x11223344)].storage_write, fields(&[1]));
+    }
+    #[test]
+    fn abi_labels() {
+        // transfer(address,uint256) writes slot 1, 0xdeadbeef writes slot 2
+        let code = deploy(&[
+            0x5f, 0x35, 0x60, 0xe0, 0x1c, 0x80, 0x63, 0xa9, 0x05, 0x9c, 0xbb, 0x14, 0x60, 0x19,
+            0x57, 0x63, 0xde, 0xad, 0xbe, 0xef, 0x14, 0x60, 0x20, 0x57, 0x00, 0x5b, 0x60, 0x01,
+            0x60, 0x01, 0x55, 0x00, 0x5b, 0x60, 0x01, 0x60, 0x02, 0x55, 0x00,
+        ]);
+        let abi = r#"[
+            {"type":"constructor","inputs":[],"stateMutability":"nonpayable"},
+            {"type":"function","name":"transfer","inputs":[{"name":"to","type":"address"},{"name":"value","type":"uint256"}],"outputs":[{"type":"bool"}],"stateMutability":"nonpayable"},
+            {"type":"function","name":"balanceOf","inputs":[{"name":"owner","type":"address"}],"outputs":[{"type":"uint256"}],"stateMutability":"view"},
+            {"type":"event","name":"Transfer","inputs":[],"anonymous":false}
+        ]"#;
+        let functions = parse_abi(abi).unwrap();
+        assert_eq!(functions.len(), 2);
+        let mut data = analyze_contract_default(&code).unwrap();
+        let report = data.apply_abi(&functions);
+        let transfer = data.methods[&U256::from(0xa9059cbbu64)].signature.clone();
+        assert_eq!(transfer.unwrap().signature(), "transfer(address,uint256)");
+        assert!(data.methods[&U256::from(0xdeadbeefu64)].signature.is_none());
+        assert_eq!(report.missing.len(), 1);
+        assert_eq!(report.missing[0].name, "balanceOf");
+        assert_eq!(report.missing[0].selector(), U256::from(0x70a08231u64));
+        assert_eq!(report.unknown, vec![U256::from(0xdeadbeefu64)]);
+    }
+    #[test]
+    fn abi_parsing() {
+        // Tuples are expanded in the signature, legacy ABIs use `constant` and `payable`
+        let abi = r#"[
+            {"type":"function","name":"swap","inputs":[{"name":"orders","type":"tuple[]","components":[{"name":"maker","type":"address"},{"name":"amounts","type":"uint256[2]"}]},{"name":"data","type":"bytes"}],"stateMutability":"payable"},
+            {"name":"totalSupply","inputs":[],"constant":true,"payable":false},
+            {"type":"function","name":"deposit","inputs":[],"constant":false,"payable":true},
+            {"type":"fallback","stateMutability":"payable"}
+        ]"#;
+        let functions = parse_abi(abi).unwrap();
+        assert_eq!(functions.len(), 3);
+        assert_eq!(
+            functions[0].signature(),
+            "swap((address,uint256[2])[],bytes)"
+        );
+        assert_eq!(functions[0].state_mutability, StateMutability::Payable);
+        assert_eq!(functions[1].state_mutability, StateMutability::View);
+        assert_eq!(functions[1].selector(), U256::from(0x18160dddu64));
+        assert_eq!(functions[2].state_mutability, StateMutability::Payable);
+        assert!(parse_abi("{}").is_err());
+    }
+    #[test]
+    fn serialization_roundtrip() {
+        let code = include_bytes!("../contracts/bytecodes/actual_contract2");
+        let data = analyze_contract_default(code).unwrap();
+        let bytes = data.to_bytes().unwrap();
+        assert_eq!(ContractData::from_bytes(&bytes).unwrap(), data);
+        let json = data.to_json().unwrap();
+        assert_eq!(ContractData::from_json(&json).unwrap(), data);
+        // Data produced by another version of the analyzer is rejected
+        let outdated = json.replacen(crate::cache::ANALYZER_VERSION, "0.0.0-old", 1);
+        match ContractData::from_json(&outdated) {
+            Err(SerializationError::Version { analyzer, .. }) => assert_eq!(analyzer, "0.0.0-old"),
+            _ => panic!("outdated data was accepted"),
+        }
+    }
+    #[test]
+    fn analysis_cache() {
+        let directory = std::env::temp_dir().join(format!("ethca-cache-{}", std::process::id()));
+        let cache = AnalysisCache::new(&directory).unwrap();
+        let code = include_bytes!("../contracts/bytecodes/simple_contract");
+        let mut runs = 0;
+        let first = cache
+            .get_or_analyze(code, |c| {
+                runs += 1;
+                analyze_contract_default(c)
+            })
+            .unwrap();
+        let second = cache
+            .get_or_analyze(code, |c| {
+                runs += 1;
+                analyze_contract_default(c)
+            })
+            .unwrap();
+        assert_eq!(runs, 1);
+        assert_eq!(first, second);
+        // Corrupted entries are treated as missing
+        for entry in std::fs::read_dir(&directory).unwrap() {
+            std::fs::write(entry.unwrap().path(), b"garbage").unwrap();
+        }
+        assert!(cache.get(code).is_none());
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+    #[test]
+    fn analysis_cache_runtime_key() {
+        let directory =
+            std::env::temp_dir().join(format!("ethca-runtime-cache-{}", std::process::id()));
+        let cache = AnalysisCache::new(&directory).unwrap();
+        // The method 0x11223344 writes slot 1
+        let runtime = [
+            0x60, 0x00, 0x35, 0x60, 0xe0, 0x1c, 0x63, 0x11, 0x22, 0x33, 0x44, 0x14, 0x60, 0x10,
+            0x57, 0x00, 0x5b, 0x60, 0x01, 0x60, 0x01, 0x55, 0x00,
+        ];
+        let analyze = |code: &[u8]| {
+            analyze_contract_cached(
+                code,
+                &NocycleSolver(),
+                &DefaultDispatcher(),
+                &(lazy_function_analyzer as FunctionAnalyzer<NoLogger>),
+                &mut NoLogger(),
+                &cache,
+            )
+            .unwrap()
+        };
+        let data = analyze(&deploy(&runtime));
+        assert_eq!(data, analyze_contract_default(&deploy(&runtime)).unwrap());
+        // The entry holds the analysis of the runtime code, keyed by its hash
+        let mut entry = cache.get(&runtime).unwrap();
+        assert_eq!(entry, analyze_runtime_code_default(&runtime).unwrap());
+        // A deployment with constructor arguments appended reads the same entry
+        entry.get_method(U256::from(0xdeadbeefu64));
+        cache.insert(&runtime, &entry).unwrap();
+        let mut with_arguments = deploy(&runtime);
+        with_arguments.extend_from_slice(&[0xab; 32]);
+        let data = analyze(&with_arguments);
+        assert!(data.methods.contains_key(&U256::from(0xdeadbeefu64)));
+        // Only the entry is left in the directory
+        assert_eq!(std::fs::read_dir(&directory).unwrap().count(), 1);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+    #[test]
+    fn runtime_code() {
+        let runtime = [
+            0x5f, 0x35, 0x60, 0xe0, 0x1c, 0x80, 0x63, 0xa9, 0x05, 0x9c, 0xbb, 0x14, 0x60, 0x19,
+            0x57, 0x63, 0xde, 0xad, 0xbe, 0xef, 0x14, 0x60, 0x20, 0x57, 0x00, 0x5b, 0x60, 0x01,
+            0x60, 0x01, 0x55, 0x00, 0x5b, 0x60, 0x01, 0x60, 0x02, 0x55, 0x00,
+        ];
+        let data = analyze_runtime_code_default(&runtime).unwrap();
+        assert_eq!(data, analyze_contract_default(&deploy(&runtime)).unwrap());
+        assert_eq!(data.constructor, ContractMethod::new());
+        assert_eq!(
+            data.methods[&U256::from(0xdeadbeefu64)].storage_write,
+            fields(&[2])
+        );
+        // Runtime code does not need to return a code section
+        assert!(analyze_contract_default(&runtime).is_err());
+    }
+    #[test]
+    fn immutables() {
+        // The owner is stored in memory, then written over the runtime code after a jump; the
+        // runtime length is computed as CODESIZE - 0x23
+        let mut code = vec![
+            0x33, 0x60, 0x80, 0x52, 0x60, 0x07, 0x56, 0x5b, 0x60, 0x23, 0x38, 0x03, 0x80, 0x60,
+            0x23, 0x60, 0x00, 0x39, 0x60, 0x80, 0x51, 0x60, 0x00, 0x60, 0x25, 0x01, 0x52, 0x60,
+            0x07, 0x60, 0x03, 0x52, 0x60, 0x00, 0xf3,
+        ];
+        // SSTORE(PUSH32 immutable, 1), PUSH32 owner
+        code.extend_from_slice(&[0x60, 0x01, 0x7f]);
+        code.extend_from_slice(&[0u8; 32]);
+        code.extend_from_slice(&[0x55, 0x7f]);
+        code.extend_from_slice(&[0u8; 32]);
+        code.extend_from_slice(&[0x50, 0x00]);
+        let data = analyze_contract_default(&code).unwrap();
+        let fallback = data.fallback.unwrap();
+        assert_eq!(fallback.storage_write, fields(&[7]));
+        // The owner is only known at deployment
+        assert!(fallback.incomplete);
+        assert_eq!(data.immutables, vec![(37, StackValue::Caller)]);
+    }
+    #[test]
+    fn immutables_at_code_offset() {
+        // The runtime code is copied at 0 and the first word is then written at 0 + 0
+        let mut code = vec![0x60, 0x26, 0x80, 0x60, 0x00, 0x60, 0x00, 0x39, 0x7f, 0x7f];
+        code.extend_from_slice(&[0u8; 31]);
+        code.extend_from_slice(&[0x60, 0x00, 0x60, 0x00, 0x01, 0x52, 0x60, 0x00, 0xf3]);
+        // STOP, unless the constructor turns it into SSTORE(PUSH32 7, 1)
+        let mut runtime = vec![0u8; 32];
+        runtime.extend_from_slice(&[0x07, 0x60, 0x01, 0x90, 0x55, 0x00]);
+        let deploy = |constructor: &[u8]| {
+            let mut code = constructor.to_vec();
+            code[4] = code.len() as u8;
+            code.extend_from_slice(&runtime);
+            analyze_contract_default(&code).unwrap()
+        };
+        let fallback = deploy(&code).fallback.unwrap();
+        assert_eq!(fallback.storage_write, fields(&[7]));
+        assert!(!fallback.incomplete);
+        // A write at an unknown offset might modify the code
+        let index = code.len() - 3;
+        code.splice(index..index, vec![0x60, 0x01, 0x33, 0x52]);
+        let fallback = deploy(&code).fallback.unwrap();
+        assert_eq!(fallback.storage_write, fields(&[7]));
+        assert!(fallback.incomplete);
+    }
+    #[test]
+    fn bytecode_metadata() {
+        let code = include_bytes!("../contracts/bytecodes/storage");
+        // The constructor ends with RETURN, followed by INVALID and the runtime code
+        let (constructor, metadata) = split_code(code);
+        assert_eq!(constructor.len(), 30);
+        let metadata = metadata.unwrap();
+        assert_eq!(metadata.compiler, "solc");
+        assert_eq!(metadata.version.as_deref(), Some("0.6.6"));
+        let (kind, hash) = metadata.source_hash.unwrap();
+        assert_eq!((kind.as_str(), hash.len()), ("ipfs", 34));
+        // INVALID, the metadata and its length are removed from the runtime code
+        let runtime = &code[31..];
+        assert_eq!(split_code(runtime).0.len(), runtime.len() - 54);
+        let data = analyze_contract_default(code).unwrap();
+        assert_eq!(data.metadata, parse_metadata(code));
+        // Vyper >= 0.3.10: [runtime size, data sizes, immutables size, {"vyper": [0, 3, 10]}]
+        let vyper = [
+            0x00, 0x84, 0x18, 0x2a, 0x81, 0x00, 0x00, 0xa1, 0x65, 0x76, 0x79, 0x70, 0x65, 0x72,
+            0x83, 0x00, 0x03, 0x0a, 0x00, 0x13,
+        ];
+        let metadata = parse_metadata(&vyper).unwrap();
+        assert_eq!(metadata.compiler, "vyper");
+        assert_eq!(metadata.version.as_deref(), Some("0.3.10"));
+        assert_eq!(split_code(&vyper).0, &[0x00]);
+        assert_eq!(parse_metadata(&[0x60, 0x00, 0x00, 0x02]), None);
+    }
+    #[test]
+    fn control_flow_graph() {
+        // Calls the function at 0x14, returning to 0x05, then loops at 0x08; 0x1b is never reached
+        let code = [
+            0x60, 0x05, 0x60, 0x14, 0x56, 0x5b, 0x60, 0x00, 0x5b, 0x60, 0x01, 0x01, 0x80, 0x60,
+            0x0a, 0x11, 0x60, 0x08, 0x57, 0x00, 0x5b, 0x60, 0x01, 0x60, 0x00, 0x55, 0x56, 0x5b,
+            0x00,
+        ];
+        let cfg = Cfg::from_code(&code);
+        assert_eq!(cfg.blocks[&5].end, 8);
+        assert_eq!(cfg.successors(0), vec![20]);
+        assert_eq!(cfg.successors(8), vec![8, 19]);
+        assert_eq!(cfg.predecessors(5), vec![20]);
+        assert!(cfg.is_dynamic_edge(20, 5));
+        assert!(!cfg.is_dynamic_edge(0, 20));
+        assert_eq!(cfg.immediate_dominator(8), Some(5));
+        assert_eq!(cfg.immediate_dominator(0), None);
+        assert!(cfg.dominates(20, 19));
+        assert!(!cfg.dominates(19, 8));
+        assert_eq!(cfg.loop_headers(), vec![8]);
+        assert_eq!(cfg.reachable_blocks(), vec![0, 5, 8, 19, 20]);
+        assert!(cfg.unresolved_jumps.is_empty());
+        let dot = cfg.to_dot();
+        assert!(dot.contains("b20 -> b5 [style=dashed];"));
+        assert!(dot.contains("b8 [label=\"0x8..0x13\", peripheries=2];"));
+        assert!(dot.contains("b27 [label=\"0x1b..0x1d\", color=grey, fontcolor=grey];"));
+        // The graph of a deployment bytecode is built for the code it returns
+        let runtime = extract_runtime_code(&deploy(&code)).unwrap();
+        assert_eq!(Cfg::from_code(&runtime).to_dot(), dot);
+    }
+    #[test]
+    fn computed_return_address() {
+        // The return address 0x08 is computed as 0x20 - 0x18 before calling the function at 0x0f
+        let code = [
+            0x60, 0x18, 0x60, 0x20, 0x03, 0x60, 0x0f, 0x56, 0x5b, 0x60, 0x01, 0x60, 0x01, 0x55,
+            0x00, 0x5b, 0x56,
+        ];
+        let functions = list_functions(&code);
+        let single = single_threded_function_analyzer(&code, &functions, &mut NoLogger());
+        let multi = multi_threded_function_analyzer(&code, &functions, &mut NoLogger());
+        for registry in [single, multi].iter() {
+            assert!(registry.skipped().is_empty());
+        }
+    }
+    #[test]
+    fn max_iterations_loop_headers() {
+        // for (i = 0; i < 3; i++) BALANCE(i), with the loop header at 0x05
+        let code = [
+            0x60, 0x00, 0x60, 0x05, 0x56, 0x5b, 0x80, 0x60, 0x03, 0x11, 0x15, 0x60, 0x17, 0x57,
+            0x80, 0x31, 0x50, 0x60, 0x01, 0x01, 0x60, 0x05, 0x56, 0x5b, 0x00,
+        ];
+        assert_eq!(Cfg::from_code(&code).loop_headers(), vec![5]);
+        let accounts = |solver: &dyn CycleSolver| {
+            analyze_runtime_code(
+                &code,
+                solver,
+                &DefaultDispatcher(),
+                &(single_threded_function_analyzer as FunctionAnalyzer<NoLogger>),
+                &mut NoLogger(),
+            )
+            .unwrap()
+            .fallback
+            .unwrap()
+            .balance_read
+        };
+        // The conditions are not evaluated, so the loop runs as many times as it is followed
+        assert_eq!(accounts(&NocycleSolver()).len(), 1);
+        assert_eq!(accounts(&MaxIterations(1)).len(), 2);
+        let read: HashSet<u64> = accounts(&MaxIterations(2))
+            .iter()
+            .map(|account| account.resolve().unwrap().as_u64())
+            .collect();
+        assert_eq!(read, [0, 1, 2].iter().cloned().collect());
+    }
+    #[test]
+    fn sha3_memory_gap() {
+        // MSTORE(0x20, 5) SLOAD(SHA3(0, 0x40) | 0): the word at 0x00 is not stored
+        let gap = vec![
+            0x60, 0x05, 0x60, 0x20, 0x52, 0x60, 0x00, 0x60, 0x40, 0x60, 0x00, 0x20, 0x17, 0x54,
+            0x00,
+        ];
+        // Same code, starting with MSTORE(0, 0)
+        let mut covered = vec![0x60, 0x00, 0x60, 0x00, 0x52];
+        covered.extend_from_slice(&gap);
+        let slots = |code: &[u8]| -> Vec<Option<U256>> {
+            analyze_runtime_code(
+                code,
+                &NocycleSolver(),
+                &DefaultDispatcher(),
+                &(single_threded_function_analyzer as FunctionAnalyzer<NoLogger>),
+                &mut NoLogger(),
+            )
+            .unwrap()
+            .fallback
+            .unwrap()
+            .storage_read
+            .iter()
+            .map(|slot| slot.value().resolve())
+            .collect()
+        };
+        let mut data = [0u8; 64];
+        data[63] = 5;
+        let hash = U256::from(&keccak256(&data)[..]);
+        assert_eq!(slots(&gap), vec![None]);
+        assert_eq!(slots(&covered), vec![Some(hash)]);
+    }
+    #[test]
+    fn unreachable_blocks() {
+        // Same code as control_flow_graph: the block at 0x1b is never reached
+        let code = [
+            0x60, 0x05, 0x60, 0x14, 0x56, 0x5b, 0x60, 0x00, 0x5b, 0x60, 0x01, 0x01, 0x80, 0x60,
+            0x0a, 0x11, 0x60, 0x08, 0x57, 0x00, 0x5b, 0x60, 0x01, 0x60, 0x00, 0x55, 0x56, 0x5b,
+            0x00,
+        ];
+        let functions = list_functions(&code);
+        let single = single_threded_function_analyzer(&code, &functions, &mut NoLogger());
+        let multi = multi_threded_function_analyzer(&code, &functions, &mut NoLogger());
+        for registry in [single, multi].iter() {
+            assert_eq!(registry.skipped(), vec![27]);
+            assert_eq!(
+                registry.stats(),
+                AnalysisStats {
+                    listed: 6,
+                    analyzed: 5,
+                    skipped: 1
+                }
+            );
+        }
+        let data = analyze_runtime_code_default(&code).unwrap();
+        assert_eq!(data.fallback.unwrap().storage_write, fields(&[0]));
+    }
+    #[test]
+    fn lazy_registry() {
+        let code = [
+            0x60, 0x05, 0x60, 0x14, 0x56, 0x5b, 0x60, 0x00, 0x5b, 0x60, 0x01, 0x01, 0x80, 0x60,
+            0x0a, 0x11, 0x60, 0x08, 0x57, 0x00, 0x5b, 0x60, 0x01, 0x60, 0x00, 0x55, 0x56, 0x5b,
+            0x00,
+        ];
+        let functions = list_functions(&code);
+        let registry = lazy_function_analyzer(&code, &functions, &mut NoLogger());
+        assert_eq!(registry.stats().analyzed, 0);
+        let function = registry.get_from_address(20).unwrap();
+        assert_eq!(function.storage_access_write.len(), 1);
+        assert!(registry.is_analyzed(20));
+        assert!(!registry.is_analyzed(0));
+        assert!(registry.get_from_address(21).is_none());
+        // The graph analyzes the blocks it reaches
+        assert_eq!(registry.cfg().loop_headers(), vec![8]);
+        assert_eq!(registry.skipped(), vec![27]);
+        // Blocks are analyzed once, even when requested by many threads
+        let registry = lazy_function_analyzer(&code, &functions, &mut NoLogger());
+        let addresses: Vec<usize> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| registry.get_from_address(8).unwrap() as *const _ as usize))
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert!(addresses.iter().all(|a| *a == addresses[0]));
+        assert_eq!(registry.stats().analyzed, 1);
+    }
+    #[test]
+    fn analyzer_config() {
+        let mut config = AnalyzerConfig::new(2);
+        // Analyze even small contracts on the pool
+        config.single_thread_below = 0;
+        let shared = AnalyzerConfig::with_pool(config.pool());
+        for name in ["simple_contract", "actual_contract2"].iter() {
+            let code = std::fs::read(format!("contracts/bytecodes/{}", name)).unwrap();
+            let expected = analyze_contract_single(&code).unwrap();
+            for analyzer in [&config, &shared].iter() {
+                let data = analyze_contract(
+                    &code,
+                    &NocycleSolver(),
+                    &DefaultDispatcher(),
+                    *analyzer,
+                    &mut NoLogger(),
+                )
+                .unwrap();
+                assert_eq!(data, expected);
+            }
+        }
+        let code = include_bytes!("../contracts/bytecodes/storage");
+        let functions = list_functions(code);
+        let registry = config.analyze(code, &functions, &mut NoLogger());
+        assert_eq!(registry.stats().analyzed, 3);
+        // The default configs, used by analyze_contract_default, share one pool
+        let default = AnalyzerConfig::default();
+        assert!(Arc::ptr_eq(
+            &default.pool(),
+            &AnalyzerConfig::default().pool()
+        ));
+        assert!(!Arc::ptr_eq(&default.pool(), &config.pool()));
+    }
+    /// Records the blocks logged, and the number of instructions executed in each one
+    #[derive(Default)]
+    struct RecordingLogger {
+        functions: Vec<(usize, usize)>,
+        contract_data: Option<ContractData>,
+        finalized: bool,
+    }
+    impl ContractLogger for RecordingLogger {
+        fn log_instruction(&mut self, _instruction: &str, _pc: usize, _stack: &EvmStack) {
+            self.functions.last_mut().unwrap().1 += 1;
+        }
+        fn log_new_function(&mut self, start: usize) {
+            self.functions.push((start, 0));
+        }
+        fn finalize_function(&mut self, _function: &EvmFunction) {}
+        fn finalize(&mut self) {
+            self.finalized = true;
+        }
+        fn log_contract_data(&mut self, contract_data: ContractData) {
+            self.contract_data = Some(contract_data);
+        }
+    }
+    impl ConcurrentLogger for RecordingLogger {
+        type Child = RecordingLogger;
+        fn child(&self) -> RecordingLogger {
+            RecordingLogger::default()
+        }
+        fn merge(&mut self, child: RecordingLogger) {
+            self.functions.extend(child.functions);
+        }
+    }
+    #[test]
+    fn concurrent_logger() {
+        let code = include_bytes!("../contracts/bytecodes/actual_contract2");
+        let functions = list_functions(code);
+        let mut single = RecordingLogger::default();
+        single_threded_function_analyzer(code, &functions, &mut single);
+        let mut multi = RecordingLogger::default();
+        multi_threded_function_analyzer(code, &functions, &mut multi);
+        assert!(!single.functions.is_empty());
+        assert_eq!(multi.functions, single.functions);
+        let mut config = AnalyzerConfig::new(2);
+        config.single_thread_below = 0;
+        let mut logger = RecordingLogger::default();
+        let data = analyze_contract(
+            code,
+            &NocycleSolver(),
+            &DefaultDispatcher(),
+            &config,
+            &mut logger,
+        )
+        .unwrap();
+        assert_eq!(logger.contract_data, Some(data.clone()));
+        assert!(logger.finalized);
+        // Blocks executed on demand while resolving the methods are logged too
+        let mut lazy = RecordingLogger::default();
+        analyze_contract(
+            code,
+            &NocycleSolver(),
+            &DefaultDispatcher(),
+            &(lazy_function_analyzer as FunctionAnalyzer<RecordingLogger>),
+            &mut lazy,
+        )
+        .unwrap();
+        assert_eq!(lazy.contract_data, Some(data));
+        assert!(!lazy.functions.is_empty());
+        assert!(lazy.functions.iter().all(|(_, executed)| *executed > 0));
+        // The logger is finalized even when the analysis fails
+        let mut logger = RecordingLogger::default();
+        assert!(analyze_contract(
+            &[0x00],
+            &NocycleSolver(),
+            &DefaultDispatcher(),
+            &config,
+            &mut logger,
+        )
+        .is_err());
+        assert!(logger.contract_data.is_none());
+        assert!(logger.finalized);
+    }
+    #[test]
+    fn concrete_evaluation() {
+        let value = |x: u64| Box::from(StackValue::ActualValue(U256::from(x)));
+        let mut calldata = vec![0xa9, 0x05, 0x9c, 0xbb];
+        calldata.extend_from_slice(&[0u8; 31]);
+        calldata.push(0x2a);
+        let context = ConcreteContext {
+            caller: Some(U256::from(0x10)),
+            calldata: Some(calldata),
+            number: Some(U256::from(1000)),
+            ..ConcreteContext::default()
+        };
+        let selector = StackValue::Shr(value(224), Box::from(StackValue::CallDataLoad(value(0))));
+        assert_eq!(selector.evaluate(&context), Some(U256::from(0xa9059cbbu64)));
+        assert_eq!(
+            StackValue::CallDataLoad(value(4)).evaluate(&context),
+            Some(U256::from(0x2a))
+        );
+        // Past the end of the calldata
+        let past_end = StackValue::CallDataLoad(value(36));
+        assert_eq!(past_end.evaluate(&context), Some(U256::zero()));
+        let size = StackValue::CallDataSize;
+        assert_eq!(size.evaluate(&context), Some(U256::from(36)));
+        let deadline = StackValue::Add(Box::from(StackValue::Number), value(5));
+        assert_eq!(deadline.evaluate(&context), Some(U256::from(1005)));
+        // Not part of the context
+        assert_eq!(StackValue::TimeStamp.evaluate(&context), None);
+        let refund = StackValue::Add(Box::from(StackValue::CallValue), value(1));
+        assert_eq!(refund.evaluate(&context), None);
+        // Slot of balances[msg.sender], with balances at slot 0
+        let words = vec![(0x80, StackValue::Caller), (0xa0, *value(0))];
+        let slot = StackValue::Sha3(U256::from(0x80), U256::from(0x40), words);
+        let mut key = [0u8; 64];
+        key[31] = 0x10;
+        let hash = U256::from(&keccak256(&key)[..]);
+        assert_eq!(slot.evaluate(&context), Some(hash));
+        // Words not covering the hashed area
+        let words = vec![(0x80, StackValue::Caller), (0xc0, *value(0))];
+        let gap = StackValue::Sha3(U256::from(0x80), U256::from(0x60), words);
+        assert_eq!(gap.evaluate(&context), None);
+        let words = vec![(0x80, StackValue::Caller), (0xa0, *value(0))];
+        let tail = StackValue::Sha3(U256::from(0x80), U256::from(0x60), words);
+        assert_eq!(tail.evaluate(&context), None);
+        let words = vec![(0x80, StackValue::Caller), (0xa0, *value(0))];
+        let shorter = StackValue::Sha3(U256::from(0x80), U256::from(0x20), words);
+        assert_eq!(shorter.evaluate(&context), None);
+        // Nothing to hash
+        let empty = StackValue::Sha3(U256::from(0x80), U256::zero(), Vec::new());
+        let hash = U256::from(&keccak256(&[])[..]);
+        assert_eq!(empty.evaluate(&context), Some(hash));
+    }
+    #[test]
+    fn constant_folding() {
+        type Binary = fn(Box<StackValue>, Box<StackValue>) -> StackValue;
+        type Ternary = fn(Box<StackValue>, Box<StackValue>, Box<StackValue>) -> StackValue;
+        let value = |x: &U256| Box::from(StackValue::ActualValue(*x));
+        let binary: Vec<(Binary, fn(U256, U256) -> U256)> = vec![
+            (StackValue::Add, reference::add),
+            (StackValue::Sub, reference::sub),
+            (StackValue::Mul, reference::mul),
+            (StackValue::Div, reference::div),
+            (StackValue::SDiv, reference::sdiv),
+            (StackValue::Mod, reference::modulo),
+            (StackValue::SMod, reference::smod),
+            (StackValue::Exp, reference::exp),
+            (StackValue::SignExtend, reference::signextend),
+            (StackValue::LT, reference::lt),
+            (StackValue::GT, reference::gt),
+            (StackValue::SLT, reference::slt),
+            (StackValue::SGT, reference::sgt),
+            (StackValue::EQ, reference::eq),
+            (StackValue::And, reference::and),
+            (StackValue::Or, reference::or),
+            (StackValue::Xor, reference::xor),
+            (StackValue::Byte, reference::byte),
+            (StackValue::ShL, reference::shl),
+            (StackValue::Shr, reference::shr),
+            (StackValue::Sar, reference::sar),
+        ];
+        let values = operands(48);
+        for (operator, expected) in &binary {
+            for a in &values {
+                for b in &values {
+                    let expr = operator(value(a), value(b));
+                    assert_eq!(expr.resolve(), Some(expected(*a, *b)), "{:?}", expr);
+                }
+            }
+        }
+        let ternary: Vec<(Ternary, fn(U256, U256, U256) -> U256)> = vec![
+            (StackValue::AddMod, reference::addmod),
+            (StackValue::MulMod, reference::mulmod),
+        ];
+        let values = operands(20);
+        for (operator, expected) in &ternary {
+            for a in &values {
+                for b in &values {
+                    for n in &values {
+                        let expr = operator(value(a), value(b), value(n));
+                        assert_eq!(expr.resolve(), Some(expected(*a, *b, *n)), "{:?}", expr);
+                    }
+                }
+            }
+        }
+        for a in &operands(48) {
+            let expr = StackValue::Not(value(a));
+            assert_eq!(expr.resolve(), Some(reference::not(*a)), "{:?}", expr);
+            let expr = StackValue::IsZero(value(a));
+            assert_eq!(expr.resolve(), Some(reference::iszero(*a)), "{:?}", expr);
+        }
+
+        // Hash of two constant words, e.g. the slot of an entry of a mapping
+        let (one, key) = (U256::from(1), U256::from(0x42));
+        let words = vec![(0x20, *value(&one)), (0x00, *value(&key))];
+        let mut data = [0u8; 64];
+        data[31] = 0x42;
+        data[63] = 1;
+        let hash = U256::from(&keccak256(&data)[..]);
+        let sha3 = |words| StackValue::Sha3(U256::zero(), U256::from(0x40), words);
+        assert_eq!(sha3(words.clone()).resolve(), Some(hash));
+        let mut unknown = words;
+        unknown[1].1 = StackValue::Caller;
+        assert_eq!(sha3(unknown).resolve(), None);
+        let expr = StackValue::Sub(Box::from(StackValue::CallValue), value(&U256::one()));
+        assert_eq!(expr.resolve(), None);
+
+        // Code copied with a length based on CODESIZE can be longer than a word
+        let code: Vec<u8> = (1..=40).collect();
+        let first_word = U256::from(&code[..32]);
+        assert_eq!(StackValue::CodeSection(code).resolve(), Some(first_word));
+    }
+    #[test]
+    fn computed_jump_target() {
+        // PUSH1 0x0a PUSH1 0x10 SUB JUMP: jumps to 0x10 - 0x0a
+        let code = [0x60, 0x0a, 0x60, 0x10, 0x03, 0x56, 0x5b, 0x00];
+        let cfg = Cfg::from_code(&code);
+        assert_eq!(cfg.successors(0), vec![6]);
+        assert!(cfg.unresolved_jumps.is_empty());
+    }
+    #[test]
+    fn replace_placeholders() {
+        let value = |x: u64| Box::from(StackValue::ActualValue(U256::from(x)));
+        let placeholder = |p: usize| Box::from(StackValue::StackPaceHolder(p));
+        let mut stack = EvmStack::new();
+        stack.push(*value(0x40));
+        stack.push(*value(0x05));
+        let memory = EvmMemory::new();
+        // Return address computed by the callee
+        let target = StackValue::Sub(placeholder(2), placeholder(1));
+        let target = target.replace_parent_call(&stack, &memory);
+        assert_eq!(target.resolve(), Some(U256::from(0x3b)));
+        let flag = Box::from(StackValue::IsZero(placeholder(2)));
+        let slot = StackValue::SLoad(Box::from(StackValue::Or(placeholder(1), flag)));
+        assert_eq!(
+            slot.replace_parent_call(&stack, &memory),
+            StackValue::SLoad(Box::from(StackValue::Or(
+                value(0x05),
+                Box::from(StackValue::IsZero(value(0x40)))
+            )))
+        );
+        let call = StackValue::Call(
+            placeholder(1),
+            placeholder(2),
+            value(0),
+            value(0),
+            value(0),
+            value(0),
+            placeholder(1),
+        );
+        let call = call.replace_parent_call(&stack, &memory);
+        assert_eq!(call.children()[0], &*value(0x05));
+        assert_eq!(call.children()[1], &*value(0x40));
+        assert_eq!(call.children()[6], &*value(0x05));
+
+        let words = vec![(0x20, *placeholder(1)), (0x00, *value(1))];
+        let hash = StackValue::Sha3(U256::zero(), U256::from(0x40), words);
+        assert_eq!(hash.children(), vec![&*placeholder(1), &*value(1)]);
+        assert_eq!(
+            hash.map_children(|child| StackValue::Not(Box::from(child.clone()))),
+            StackValue::Sha3(
+                U256::zero(),
+                U256::from(0x40),
+                vec![
+                    (0x20, StackValue::Not(placeholder(1))),
+                    (0x00, StackValue::Not(value(1)))
+                ]
+            )
+        );
+        assert!(StackValue::Caller.children().is_empty());
+        let caller = StackValue::Caller.map_children(|_| unreachable!());
+        assert_eq!(caller, StackValue::Caller);
+    }
+    #[test]
+    fn stack_value_visitor() {
+        let value = |x: u64| Box::from(StackValue::ActualValue(U256::from(x)));
+        // balances[msg.data[4..36]] + SLOAD(1), with the key taken from the caller's stack
+        let key = Box::from(StackValue::CallDataLoad(value(4)));
+        let words = vec![(0, *key), (32, StackValue::StackPaceHolder(2))];
+        let entry = StackValue::Sha3(U256::zero(), U256::from(64), words);
+        let slot = StackValue::Add(
+            Box::from(StackValue::SLoad(Box::from(entry))),
+            Box::from(StackValue::SLoad(value(1))),
+        );
+        assert_eq!(slot.size(), 8);
+        assert_eq!(slot.depth(), 5);
+        assert!(slot.contains(&|v| *v == StackValue::StackPaceHolder(2)));
+        assert!(!slot.contains(&|v| *v == StackValue::Caller));
+        let free = slot.free_variables();
+        assert_eq!(free.len(), 4);
+        assert_eq!(free[1], &StackValue::CallDataLoad(value(4)));
+        assert_eq!(free[3], &StackValue::SLoad(value(1)));
+        let constants = slot.fold(&mut |v, children: Vec<Vec<U256>>| match v {
+            StackValue::ActualValue(x) => vec![*x],
+            _ => children.concat(),
+        });
+        assert_eq!(constants, vec![U256::from(4), U256::from(1)]);
+
+        /// Collects the slots read from the storage, skipping the nested reads
+        struct StorageReads<'a>(Vec<&'a StackValue>);
+        impl<'a> StackValueVisitor<'a> for StorageReads<'a> {
+            fn visit(&mut self, value: &'a StackValue) -> bool {
+                match value {
+                    StackValue::SLoad(slot) => {
+                        self.0.push(slot);
+                        false
+                    }
+                    _ => true,
+                }
+            }
+        }
+        let nested = StackValue::SLoad(Box::from(StackValue::SLoad(value(3))));
+        let mut reads = StorageReads(Vec::new());
+        let both = StackValue::Xor(Box::from(slot), Box::from(nested));
+        both.walk(&mut reads);
+        assert_eq!(reads.0.len(), 3);
+        assert_eq!(reads.0[2], &StackValue::SLoad(value(3)));
+    }
+    #[test]
+    fn create2_address() {
+        // Example 0 from EIP-1014
+        let creation = ContractCreation::Create2(
+            StackValue::CodeSection(vec![0x00]),
+            StackValue::ActualValue(U256::zero()),
+        );
+        assert_eq!(
+            creation.address(U256::zero()),
+            Some(U256::from("4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38"))
+        );
+    }
+
+    /// Operands of the constant folding tests: edge cases followed by pseudo-random words
+    fn operands(count: usize) -> Vec<U256> {
+        let max = !U256::zero();
+        let sign = U256::one() << 255;
+        let mut values: Vec<U256> = [0u64, 1, 2, 7, 8, 31, 32, 255, 256]
+            .iter()
+            .map(|x| U256::from(*x))
+            .collect();
+        values.extend(vec![max, max - 1, sign, sign - 1, sign + 1]);
+        // xorshift64
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        while values.len() < count {
+            let word = U256([next(), next(), next(), next()]);
+            // Mix small, positive and negative values
+            values.push(match next() % 4 {
+                0 => U256::from(next() % 300),
+                1 => word >> (next() % 256) as usize,
+                2 => !(word >> (next() % 256) as usize),
+                _ => word,
+            });
+        }
+        values
+    }
+
+    /// Straightforward implementations of the EVM operators, computed on 512 bits
+    mod reference {
+        use ethereum_types::{U256, U512};
+        use std::convert::TryFrom;
+
+        fn modulus() -> U512 {
+            U512::one() << 256
+        }
+        fn wide(x: U256) -> U512 {
+            U512::from(x)
+        }
+        fn narrow(x: U512) -> U256 {
+            U256::try_from(x % modulus()).unwrap()
+        }
+        fn boolean(value: bool) -> U256 {
+            U256::from(value as u8)
+        }
+        /// Sign and magnitude of a two's complement word
+        fn signed(x: U256) -> (bool, U512) {
+            if x.bit(255) {
+                (true, modulus() - wide(x))
+            } else {
+                (false, wide(x))
+            }
+        }
+        fn unsigned(negative: bool, magnitude: U512) -> U256 {
+            if negative {
+                narrow(modulus() - magnitude)
+            } else {
+                narrow(magnitude)
+            }
+        }
+
+        pub fn add(a: U256, b: U256) -> U256 {
+            narrow(wide(a) + wide(b))
+        }
+        pub fn sub(a: U256, b: U256) -> U256 {
+            narrow(wide(a) + modulus() - wide(b))
+        }
+        pub fn mul(a: U256, b: U256) -> U256 {
+            narrow(wide(a) * wide(b))
+        }
+        pub fn div(a: U256, b: U256) -> U256 {
+            if b.is_zero() {
+                U256::zero()
+            } else {
+                a / b
+            }
+        }
+        pub fn modulo(a: U256, b: U256) -> U256 {
+            if b.is_zero() {
+                U256::zero()
+            } else {
+                a % b
+            }
+        }
+        pub fn sdiv(a: U256, b: U256) -> U256 {
+            if b.is_zero() {
+                return U256::zero();
+            }
+            let ((sign_a, a), (sign_b, b)) = (signed(a), signed(b));
+            unsigned(sign_a != sign_b, a / b)
+        }
+        pub fn smod(a: U256, b: U256) -> U256 {
+            if b.is_zero() {
+                return U256::zero();
+            }
+            let ((sign_a, a), (_, b)) = (signed(a), signed(b));
+            unsigned(sign_a, a % b)
+        }
+        pub fn addmod(a: U256, b: U256, n: U256) -> U256 {
+            if n.is_zero() {
+                return U256::zero();
+            }
+            narrow((wide(a) + wide(b)) % wide(n))
+        }
+        pub fn mulmod(a: U256, b: U256, n: U256) -> U256 {
+            if n.is_zero() {
+                return U256::zero();
+            }
+            narrow(wide(a) * wide(b) % wide(n))
+        }
+        pub fn exp(a: U256, b: U256) -> U256 {
+            let mut result = U256::one();
+            for bit in (0..256).rev() {
+                result = mul(result, result);
+                if b.bit(bit) {
+                    result = mul(result, a);
+                }
+            }
+            result
+        }
+        pub fn signextend(b: U256, x: U256) -> U256 {
+            if b >= U256::from(31) {
+                return x;
+            }
+            let mut bytes = [0u8; 32];
+            x.to_big_endian(&mut bytes);
+            let sign_byte = 31 - b.as_usize();
+            let fill = if bytes[sign_byte] & 0x80 != 0 {
+                0xff
+            } else {
+                0
+            };
+            for byte in &mut bytes[..sign_byte] {
+                *byte = fill;
+            }
+            U256::from(&bytes[..])
+        }
+        pub fn lt(a: U256, b: U256) -> U256 {
+            boolean(a < b)
+        }
+        pub fn gt(a: U256, b: U256) -> U256 {
+            boolean(a > b)
+        }
+        pub fn slt(a: U256, b: U256) -> U256 {
+            boolean(match (signed(a), signed(b)) {
+                ((true, _), (false, _)) => true,
+                ((false, _), (true, _)) => false,
+                ((true, a), (true, b)) => a > b,
+                ((false, a), (false, b)) => a < b,
+            })
+        }
+        pub fn sgt(a: U256, b: U256) -> U256 {
+            slt(b, a)
+        }
+        pub fn eq(a: U256, b: U256) -> U256 {
+            boolean(a == b)
+        }
+        pub fn iszero(a: U256) -> U256 {
+            boolean(a.is_zero())
+        }
+        pub fn and(a: U256, b: U256) -> U256 {
+            a & b
+        }
+        pub fn or(a: U256, b: U256) -> U256 {
+            a | b
+        }
+        pub fn xor(a: U256, b: U256) -> U256 {
+            a ^ b
+        }
+        pub fn not(a: U256) -> U256 {
+            narrow(modulus() - U512::one() - wide(a))
+        }
+        pub fn byte(i: U256, x: U256) -> U256 {
+            if i >= U256::from(32) {
+                return U256::zero();
+            }
+            let mut bytes = [0u8; 32];
+            x.to_big_endian(&mut bytes);
+            U256::from(bytes[i.as_usize()])
+        }
+        pub fn shl(shift: U256, x: U256) -> U256 {
+            if shift >= U256::from(256) {
+                return U256::zero();
+            }
+            narrow(wide(x) << shift.as_usize())
+        }
+        pub fn shr(shift: U256, x: U256) -> U256 {
+            if shift >= U256::from(256) {
+                return U256::zero();
+            }
+            narrow(wide(x) >> shift.as_usize())
+        }
+        pub fn sar(shift: U256, x: U256) -> U256 {
+            if x.bit(255) {
+                not(shr(shift, not(x)))
+            } else {
+                shr(shift, x)
+            }
+        }
+    }
 }
@@ -0,0 +1,213 @@
+use ethca::cfg::Cfg;
+use ethca::contract_analyzer::{analyze_contract, analyze_runtime_code, extract_runtime_code};
+use ethca::contract_data::ContractData;
+use ethca::contract_logger::NoLogger;
+use ethca::cycle_resolution::{CycleSolver, MaxIterations, NocycleSolver};
+use ethca::dispatcher::DefaultDispatcher;
+use ethca::function_analyzer::{
+    single_threded_function_analyzer, AnalyzerConfig, BlockAnalyzer, FunctionAnalyzer,
+};
+use std::env;
+use std::fs;
+use std::io::{self, Read};
+use std::process;
+
+const USAGE: &str = "Usage: ethca [OPTIONS] [FILE]
+
+Analyzes the bytecode in FILE (or standard input when FILE is missing or `-`) and prints the
+storage accesses and external calls of every method. The bytecode can be given as hex, with or
+without the 0x prefix, or as raw bytes.
+
+Options:
+    --format <text|json>      Output format (default: text)
+    --max-iterations <N>      Follow each cycle up to N times instead of never re-entering it
+    --single-thread           Analyze the functions of the contract on the current thread
+    --workers <N>             Threads analyzing the functions of the contract (default: 4); contracts
+                              smaller than 4096 bytes are analyzed on the current thread
+    --runtime-only            The bytecode is runtime code, without a constructor
+    --dot                     Print the control flow graph of the runtime code in Graphviz DOT format
+    -h, --help                Print this message";
+
+enum Format {
+    Text,
+    Json,
+}
+
+struct Options {
+    input: Option<String>,
+    format: Format,
+    max_iterations: Option<usize>,
+    single_thread: bool,
+    workers: u32,
+    runtime_only: bool,
+    dot: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        input: None,
+        format: Format::Text,
+        max_iterations: None,
+        single_thread: false,
+        workers: 4,
+        runtime_only: false,
+        dot: false,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "--format" => {
+                options.format = match args.next().as_deref() {
+                    Some("text") => Format::Text,
+                    Some("json") => Format::Json,
+                    _ => return Err(String::from("--format expects `text` or `json`")),
+                }
+            }
+            "--max-iterations" => {
+                let value = args.next().and_then(|n| n.parse().ok());
+                match value {
+                    Some(n) => options.max_iterations = Some(n),
+                    None => return Err(String::from("--max-iterations expects a number")),
+                }
+            }
+            "--single-thread" => options.single_thread = true,
+            "--workers" => {
+                let value = args.next().and_then(|n| n.parse().ok());
+                match value {
+                    Some(n) if n > 0 => options.workers = n,
+                    _ => return Err(String::from("--workers expects a positive number")),
+                }
+            }
+            "--runtime-only" => options.runtime_only = true,
+            "--dot" => options.dot = true,
+            _ if arg.starts_with("--") => return Err(format!("unknown option {}", arg)),
+            _ if options.input.is_none() => options.input = Some(arg),
+            _ => return Err(format!("unexpected argument {}", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn read_input(input: &Option<String>) -> io::Result<Vec<u8>> {
+    match input.as_deref() {
+        None | Some("-") => {
+            let mut buffer = Vec::new();
+            io::stdin().read_to_end(&mut buffer)?;
+            Ok(buffer)
+        }
+        Some(path) => fs::read(path),
+    }
+}
+
+/// Decodes the input as hex when it looks like text, otherwise it is taken as raw bytecode
+///
+/// Text starting with `0x` is expected to be valid hex, text without the prefix is decoded as hex
+/// only when it is made of hex digits.
+fn decode_bytecode(input: Vec<u8>) -> Result<Vec<u8>, String> {
+    let text = match std::str::from_utf8(&input) {
+        Ok(text) => text.trim(),
+        Err(_) => return Ok(input),
+    };
+    let digits = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(digits) => digits,
+        None if !text.is_empty() && text.chars().all(|c| c.is_ascii_hexdigit()) => text,
+        None => return Ok(input),
+    };
+    if let Some(position) = digits.find(|c: char| !c.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex digit at position {}", position));
+    }
+    if digits.len() % 2 != 0 {
+        return Err(String::from("odd number of hex digits"));
+    }
+    Ok((0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&digits[i..i + 2], 16).unwrap())
+        .collect())
+}
+
+fn run(options: Options) -> Result<(), String> {
+    let input = read_input(&options.input).map_err(|e| e.to_string())?;
+    let code = decode_bytecode(input)?;
+    if options.dot {
+        // The graph of a deployment bytecode would only show the constructor
+        let code = if options.runtime_only {
+            code
+        } else {
+            extract_runtime_code(&code).map_err(|e| e.to_string())?
+        };
+        print!("{}", Cfg::from_code(&code).to_dot());
+        return Ok(());
+    }
+    let solver: Box<dyn CycleSolver> = match options.max_iterations {
+        Some(n) => Box::from(MaxIterations(n)),
+        None => Box::from(NocycleSolver()),
+    };
+    let analyzer: Box<dyn BlockAnalyzer<NoLogger>> = if options.single_thread {
+        Box::from(single_threded_function_analyzer as FunctionAnalyzer<NoLogger>)
+    } else {
+        Box::from(AnalyzerConfig::new(options.workers))
+    };
+    let analyze = if options.runtime_only {
+        analyze_runtime_code
+    } else {
+        analyze_contract
+    };
+    let data: ContractData = analyze(
+        &code,
+        &*solver,
+        &DefaultDispatcher(),
+        &*analyzer,
+        &mut NoLogger(),
+    )
+    .map_err(|e| e.to_string())?;
+    match options.format {
+        Format::Text => data.display(),
+        Format::Json => println!("{}", data.to_json().map_err(|e| e.to_string())?),
+    }
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args().and_then(run);
+    if let Err(e) = result {
+        eprintln!("ethca: {}", e);
+        process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode_bytecode;
+
+    #[test]
+    fn hex_input() {
+        let decoded = decode_bytecode(b"6080604052\n".to_vec());
+        assert_eq!(decoded, Ok(vec![0x60, 0x80, 0x60, 0x40, 0x52]));
+        let decoded = decode_bytecode(b"0x6080604052".to_vec());
+        assert_eq!(decoded, Ok(vec![0x60, 0x80, 0x60, 0x40, 0x52]));
+        assert_eq!(decode_bytecode(b"0X00".to_vec()), Ok(vec![0x00]));
+        assert_eq!(decode_bytecode(b"0x".to_vec()), Ok(vec![]));
+    }
+    #[test]
+    fn invalid_hex_input() {
+        assert!(decode_bytecode(b"0x60806".to_vec()).is_err());
+        assert!(decode_bytecode(b"0x6080zz".to_vec()).is_err());
+        assert!(decode_bytecode(b"60806".to_vec()).is_err());
+    }
+    #[test]
+    fn raw_input() {
+        // PUSH1 0x80 PUSH1 0x40 MSTORE is not valid UTF-8
+        let raw = vec![0x60, 0x80, 0x60, 0x40, 0x52];
+        assert_eq!(decode_bytecode(raw.clone()), Ok(raw));
+        // Valid UTF-8, but not made of letters and digits
+        let raw = vec![0x60, 0x01, 0x60, 0x00, 0x55, 0x00];
+        assert_eq!(decode_bytecode(raw.clone()), Ok(raw));
+        // Letters and digits, but not only hex digits
+        let raw = b"60806g".to_vec();
+        assert_eq!(decode_bytecode(raw.clone()), Ok(raw));
+    }
+}
@@ -1,12 +1,30 @@
 use super::transaction::Transaction;
 use scoped_threadpool::Pool;
 use std::sync::mpsc::{channel, Receiver, Sender};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Runs the dependency net on a pool of 4 threads, shared by every call
+///
+/// The pool is not the one of the default `AnalyzerConfig`, so the transactions can analyze code
+/// with it; nets executed at the same time take turns.
 pub fn execute_net(start: Vec<Arc<Mutex<Transaction>>>, tot_transactions: usize) {
-    let mut pool = Pool::new(4);
+    static POOL: OnceLock<Mutex<Pool>> = OnceLock::new();
+    let pool = POOL.get_or_init(|| Mutex::from(Pool::new(4)));
+    execute_net_with_pool(start, tot_transactions, pool);
+}
+
+/// Runs the dependency net on the threads of `pool`, which can be reused across blocks
+///
+/// The pool can be shared with an `AnalyzerConfig`, passing `&config.pool()`; it is locked until
+/// every transaction has run, so the transactions must not analyze code with the same config.
+pub fn execute_net_with_pool(
+    start: Vec<Arc<Mutex<Transaction>>>,
+    tot_transactions: usize,
+    pool: &Mutex<Pool>,
+) {
     let (sender, receiver) = channel::<Arc<Mutex<Transaction>>>();
     let start_tx = sender.clone();
-    pool.scoped(|s| {
+    pool.lock().unwrap().scoped(|s| {
         s.execute(move || {
             for t in start {
                 start_tx.send(t.clone()).unwrap();
@@ -1,29 +1,110 @@
 use super::runtime_delegation::RuntimeDelegationState;
 use super::transaction::{MethodType, RunningFunction, Transaction, TransactionDataProvider};
+use crate::analysis_error::AnalysisError;
+use crate::cache::AnalysisCache;
+use crate::contract_analyzer::{analyze_contract, analyze_contract_cached};
 use crate::contract_data::{ContractData, ContractMethod};
+use crate::contract_logger::NoLogger;
+use crate::contract_utils::DataType;
+use crate::cycle_resolution::NocycleSolver;
+use crate::dispatcher::DefaultDispatcher;
+use crate::evm_types::{ConcreteContext, StackValue};
+use crate::function_analyzer::AnalyzerConfig;
 use ethereum_types::U256;
 use std::collections::HashMap;
+use std::fmt;
 use std::sync::{Arc, Mutex};
 pub struct NetBuilder {
     contracts: HashMap<U256, ContractStorage>,
     contract_data: HashMap<U256, ContractData>,
     runtime_dependent: Vec<(U256, U256, Arc<Mutex<Transaction>>)>,
     zero_deps: Vec<Arc<Mutex<Transaction>>>,
+    /// Transactions deploying a contract at a known address
+    created_contracts: HashMap<U256, Arc<Mutex<Transaction>>>,
+    /// Transactions deploying contracts whose address could not be determined
+    unresolved_creations: Vec<Arc<Mutex<Transaction>>>,
+    /// Balances of the accounts, modelled as a storage with a location per account
+    balances: ContractStorage,
+    /// Last transaction sent by each account, together with its nonce
+    senders: HashMap<U256, (Option<U256>, Arc<Mutex<Transaction>>)>,
     counter: usize,
     /// How many dependencies can we assume before delegating to runtime
     pub threshold: usize,
+    /// How to handle transactions and calls to accounts without a registered contract
+    pub unknown_contract: UnknownCodePolicy,
+    /// How to handle transactions and calls to methods missing from a registered contract
+    pub unknown_method: UnknownCodePolicy,
+    /// Results of previous analyses, used by `register_code`
+    pub cache: Option<AnalysisCache>,
+    /// Thread pool used by `register_code`; without it contracts are analyzed with the default
+    /// `AnalyzerConfig`, as by `analyze_contract_default`
+    pub analyzer: Option<AnalyzerConfig>,
 }
+
+/// How to handle the execution of code that was not analyzed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnknownCodePolicy {
+    /// Assume the code does not access any storage, as for externally owned accounts
+    Ignore,
+    /// Serialize the transaction against every other transaction accessing the same contract
+    ///
+    /// External calls performed by the unknown code are not tracked.
+    Serialize,
+}
+
+/// Reasons why a transaction could not be added to the dependency net
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetBuilderError {
+    /// The transaction was added after a transaction of the same sender with an equal or higher
+    /// nonce
+    NonceOutOfOrder {
+        sender: U256,
+        nonce: U256,
+        previous: U256,
+    },
+}
+
+impl fmt::Display for NetBuilderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NetBuilderError::NonceOutOfOrder {
+                sender,
+                nonce,
+                previous,
+            } => write!(
+                f,
+                "transaction of {:#x} with nonce {} added after nonce {}",
+                sender, nonce, previous
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NetBuilderError {}
+
 #[derive(Clone)]
 pub struct ContractStorage {
     pub contructor_transition: Option<Arc<Mutex<Transaction>>>,
     pub storage_write: HashMap<U256, Vec<Arc<Mutex<Transaction>>>>,
     pub storage_read: HashMap<U256, Vec<Arc<Mutex<Transaction>>>>,
+    /// Transactions that might have accessed any storage location of the contract
+    pub unknown_access: Vec<Arc<Mutex<Transaction>>>,
+    /// Transactions that might have deployed the contract
+    pub creators: Vec<Arc<Mutex<Transaction>>>,
+    /// Transactions reading a known entry of a mapping, by base slot of the mapping
+    pub mapping_read: HashMap<U256, Vec<Arc<Mutex<Transaction>>>>,
+    /// Transactions writing a known entry of a mapping, by base slot of the mapping
+    pub mapping_write: HashMap<U256, Vec<Arc<Mutex<Transaction>>>>,
 }
 impl ContractStorage {
     fn new() -> Self {
         ContractStorage {
             storage_write: HashMap::new(),
             storage_read: HashMap::new(),
+            mapping_read: HashMap::new(),
+            mapping_write: HashMap::new(),
+            unknown_access: Vec::new(),
+            creators: Vec::new(),
             contructor_transition: None,
         }
     }
@@ -31,6 +112,22 @@ impl ContractStorage {
 
 // TODO: add dependencies to constructors
 
+/// A method executed while running a transaction
+struct Frame<'a> {
+    /// The contract whose storage is accessed by the method
+    storage: U256,
+    /// The contract whose code is executed
+    code: U256,
+    /// The analysis of the method, if available
+    method: Option<&'a ContractMethod>,
+    /// The selector used to invoke the method
+    selector: Option<U256>,
+    /// Whether the method is executed in a read only context
+    read_only: bool,
+    /// The values known while executing the method, e.g. its caller
+    context: ConcreteContext,
+}
+
 impl NetBuilder {
     pub fn new() -> Self {
         NetBuilder {
@@ -38,20 +135,76 @@ impl NetBuilder {
             contract_data: HashMap::new(),
             runtime_dependent: Vec::new(),
             zero_deps: Vec::new(),
+            created_contracts: HashMap::new(),
+            unresolved_creations: Vec::new(),
+            balances: ContractStorage::new(),
+            senders: HashMap::new(),
             counter: 0,
             threshold: 10,
+            unknown_contract: UnknownCodePolicy::Ignore,
+            unknown_method: UnknownCodePolicy::Serialize,
+            cache: None,
+            analyzer: None,
         }
     }
+    /// Registers the analysis of the contract deployed at `address`
+    ///
+    /// Contracts registered after a transaction that deploys a contract at an unknown address
+    /// are assumed to be possibly deployed by it.
     pub fn register_contract(&mut self, address: U256, contract: ContractData) {
-        self.contracts.insert(address, ContractStorage::new());
+        // Keep the dependencies of transactions sent before the contract was registered
+        let storage = self
+            .contracts
+            .entry(address)
+            .or_insert_with(ContractStorage::new);
+        storage
+            .creators
+            .extend(self.unresolved_creations.iter().cloned());
+        if let Some(creator) = self.created_contracts.get(&address) {
+            storage.creators.push(creator.clone());
+        }
         self.contract_data.insert(address, contract);
     }
 
+    /// Analyzes `code` with `analyze_contract_default`, or with the `analyzer` if set, and
+    /// registers it at `address`
+    ///
+    /// When a cache is set only the constructor is analyzed for runtime code that was analyzed
+    /// before.
+    pub fn register_code(&mut self, address: U256, code: &[u8]) -> Result<(), AnalysisError> {
+        let analyzer = self.analyzer.clone().unwrap_or_default();
+        let contract = match &self.cache {
+            Some(cache) => analyze_contract_cached(
+                code,
+                &NocycleSolver(),
+                &DefaultDispatcher(),
+                &analyzer,
+                &mut NoLogger(),
+                cache,
+            )?,
+            None => analyze_contract(
+                code,
+                &NocycleSolver(),
+                &DefaultDispatcher(),
+                &analyzer,
+                &mut NoLogger(),
+            )?,
+        };
+        self.register_contract(address, contract);
+        Ok(())
+    }
+
+    /// Adds a transaction to the dependency net
+    ///
+    /// Transactions must be added in the order they appear in the block; in particular the
+    /// transactions of the same sender must be added in nonce order. A transaction whose nonce
+    /// is not higher than the one of the last transaction of its sender is rejected with
+    /// `NonceOutOfOrder`, without changing the net.
     pub fn new_transaction(
         &mut self,
         transaction_data: &dyn TransactionDataProvider,
         run: RunningFunction,
-    ) {
+    ) -> Result<(), NetBuilderError> {
         /*println!(
             "Analyzing transaction {} :: {:?}",
             transaction_data.get_target_contract(),
@@ -60,11 +213,20 @@ impl NetBuilder {
         // Retrive Transaction Informations
         let contract = transaction_data.get_target_contract();
         let method = transaction_data.get_target_method();
-        let contract_data = &self.contract_data[&contract];
-        let method_data = match method {
-            MethodType::Method(x) => &contract_data.methods[&x],
-            MethodType::Constructor => &contract_data.constructor,
-        };
+        let sender = transaction_data.get_sender();
+        let nonce = transaction_data.get_nonce();
+        if let Some(s) = sender {
+            if let (Some(n), Some((Some(last_nonce), _))) = (nonce, self.senders.get(&s)) {
+                if n <= *last_nonce {
+                    return Err(NetBuilderError::NonceOutOfOrder {
+                        sender: s,
+                        nonce: n,
+                        previous: *last_nonce,
+                    });
+                }
+            }
+        }
+        let method_data = Self::lookup_method(&self.contract_data, contract, &method);
 
         // Create the transaction
         let transaction = Arc::from(Mutex::from(Transaction::new(self.counter, run)));
@@ -73,44 +235,198 @@ impl NetBuilder {
         }
         self.counter += 1;
 
-        let mut methods_to_analyze = vec![(contract, method_data)];
+        // Transactions of the same sender are executed in nonce order
+        if let Some(s) = sender {
+            let mut last_nonce = None;
+            if let Some((previous, last)) = self.senders.get(&s) {
+                last.lock().unwrap().required_by(transaction.clone());
+                last_nonce = *previous;
+            }
+            // Keep the last known nonce to check the following transactions
+            self.senders
+                .insert(s, (nonce.or(last_nonce), transaction.clone()));
+        }
+
+        // The sender pays for the gas and the transferred value goes to the target
+        let mut balance_write = Vec::new();
+        if let Some(s) = sender {
+            balance_write.push(s);
+        }
+        if transaction_data.get_value().is_none_or(|v| !v.is_zero()) {
+            balance_write.push(contract);
+        }
+        Self::analyze_locations(
+            &mut self.balances,
+            &transaction,
+            Vec::new(),
+            balance_write,
+            false,
+        );
+
+        let selector = match method {
+            MethodType::Method(x) => Some(x),
+            _ => None,
+        };
+        let mut methods_to_analyze = vec![Frame {
+            storage: contract,
+            code: contract,
+            method: method_data,
+            selector,
+            read_only: false,
+            context: transaction_data.get_context(),
+        }];
         let mut methods_analyzed = vec![]; // Keep a list of analyzed methods to avoid cycles
         let mut constructor_analyzed = vec![]; // Keep a list of analyzed contracts to avoid cycles on contructors
         if let MethodType::Method(x) = method {
             methods_analyzed.push((contract, x));
         } else {
             self.contracts
-                .get_mut(&contract)
-                .unwrap()
+                .entry(contract)
+                .or_insert_with(ContractStorage::new)
                 .contructor_transition = Some(transaction.clone());
             constructor_analyzed.push(contract);
         }
         while !methods_to_analyze.is_empty() {
-            let method_data = methods_to_analyze.pop().unwrap();
-           //println!("Analyzing method {}", method_data.0);
-            let contract_d = self.contracts.get_mut(&method_data.0).unwrap();
-            if !constructor_analyzed.contains(&method_data.0) {
-               //println!("Adding constructor dependency");
-                contract_d
-                    .contructor_transition
-                    .as_ref()
-                    .unwrap()
-                    .lock()
-                    .unwrap()
-                    .required_by(transaction.clone());
-            }
-            // Resolve dependencies for method access
-            Self::analyze_method(method_data.1, contract_d, &transaction);
+            let frame = methods_to_analyze.pop().unwrap();
+           //println!("Analyzing method {}", frame.storage);
+            let policy = if self.contract_data.contains_key(&frame.code) {
+                self.unknown_method
+            } else {
+                self.unknown_contract
+            };
+            let serialize = frame.method.is_none() && policy == UnknownCodePolicy::Serialize;
+            if serialize {
+                self.contracts
+                    .entry(frame.storage)
+                    .or_insert_with(ContractStorage::new);
+            }
+            if let Some(contract_d) = self.contracts.get_mut(&frame.storage) {
+                if !constructor_analyzed.contains(&frame.storage) {
+                   //println!("Adding constructor dependency");
+                    // Contracts deployed by other contracts have no constructor transaction
+                    if let Some(constructor) = &contract_d.contructor_transition {
+                        constructor.lock().unwrap().required_by(transaction.clone());
+                    }
+                }
+                // The contract must be deployed before it is used
+                for creator in &contract_d.creators {
+                    if !Arc::ptr_eq(creator, &transaction) {
+                        creator.lock().unwrap().required_by(transaction.clone());
+                    }
+                }
+                // Resolve dependencies for method access
+                match frame.method {
+                    Some(m) => Self::analyze_method(m, contract_d, &transaction, &frame),
+                    None if serialize => Self::analyze_unknown_access(contract_d, &transaction),
+                    None => {}
+                }
+            }
+            let method_data = match frame.method {
+                Some(m) => m,
+                None => {
+                    // The unknown code might spend the balance of the contract
+                    if serialize && !frame.read_only {
+                        Self::analyze_locations(
+                            &mut self.balances,
+                            &transaction,
+                            Vec::new(),
+                            vec![frame.storage],
+                            false,
+                        );
+                    }
+                    continue;
+                }
+            };
+
+            // Resolve dependencies for balance access
+            let mut conservative = false;
+            let mut balance_read = Vec::new();
+            for account in &method_data.balance_read {
+                match account.evaluate(&frame.context) {
+                    Some(address) => balance_read.push(address),
+                    None => conservative = true,
+                }
+            }
+            let mut balance_write = Vec::new();
+            // Transfers are not allowed in a static context, the call would revert
+            for account in method_data
+                .balance_write
+                .iter()
+                .filter(|_| !frame.read_only)
+            {
+                match account.evaluate(&frame.context) {
+                    Some(address) => balance_write.push(address),
+                    None => conservative = true,
+                }
+            }
+            Self::analyze_locations(
+                &mut self.balances,
+                &transaction,
+                balance_read,
+                balance_write,
+                conservative,
+            );
+
+            // Register deployed contracts
+            for creation in &method_data.contract_creation {
+                // The deployer is the contract executing the code
+                if let Some(address) = creation.address(frame.storage) {
+                    self.created_contracts.insert(address, transaction.clone());
+                    if let Some(created) = self.contracts.get_mut(&address) {
+                        created.creators.push(transaction.clone());
+                    }
+                } else if !self
+                    .unresolved_creations
+                    .last()
+                    .is_some_and(|last| Arc::ptr_eq(last, &transaction))
+                {
+                    self.unresolved_creations.push(transaction.clone());
+                }
+            }
 
             // Resolve external Calls
-            for call in &method_data.1.method_call {
+            for call in &method_data.method_call {
                 let contract_addr = call.0.resolve();
-                let method_opt = call.1.resolve();
+                let (method_opt, call_data) = match &call.1 {
+                    // The calldata is forwarded unchanged (e.g. proxies)
+                    StackValue::CalldataCopy(_, offset)
+                        if offset.resolve() == Some(U256::zero()) =>
+                    {
+                        (frame.selector, frame.context.calldata.clone())
+                    }
+                    calldata => (calldata.resolve(), None),
+                };
+                // Delegated calls execute the callee code on the storage of the caller
+                let delegated = call.2.is_delegated();
+                let read_only = frame.read_only || call.2.is_read_only();
+                let context = |storage: U256| {
+                    let mut context = frame.context.clone();
+                    context.address = Some(storage);
+                    context.calldata = call_data.clone();
+                    if !delegated {
+                        context.caller = Some(frame.storage);
+                        // The value sent by the call is not known
+                        context.value = None;
+                    }
+                    context
+                };
                 if let Some(method_2) = method_opt {
                     if let Some(c) = contract_addr {
                         // If we can resolve the contract hash
-                        let new_method = &self.contract_data[&c].methods[&method_2];
-                        methods_to_analyze.push((c, new_method));
+                        let new_method = Self::lookup_method(
+                            &self.contract_data,
+                            c,
+                            &MethodType::Method(method_2),
+                        );
+                        let storage = if delegated { frame.storage } else { c };
+                        methods_to_analyze.push(Frame {
+                            storage,
+                            code: c,
+                            method: new_method,
+                            selector: Some(method_2),
+                            read_only,
+                            context: context(storage),
+                        });
                     } else {
                         let mut compatible = Vec::new();
                         // Otherwise add dependency to all
@@ -119,7 +435,15 @@ impl NetBuilder {
                             for m in &c.1.methods {
                                 if *m.0 == method_2 {
                                     // If they have the same signature
-                                    compatible.push((*c.0, m.1))
+                                    let storage = if delegated { frame.storage } else { *c.0 };
+                                    compatible.push(Frame {
+                                        storage,
+                                        code: *c.0,
+                                        method: Some(m.1),
+                                        selector: Some(method_2),
+                                        read_only,
+                                        context: context(storage),
+                                    })
                                 }
                             }
                         }
@@ -151,21 +475,147 @@ impl NetBuilder {
         if *transaction.lock().unwrap().count.lock().unwrap() == 0 {
             self.zero_deps.push(transaction.clone())
         }
+        Ok(())
+    }
+
+    /// Looks up the analysis of the code executed when calling `method` on `address`
+    fn lookup_method<'a>(
+        contract_data: &'a HashMap<U256, ContractData>,
+        address: U256,
+        method: &MethodType,
+    ) -> Option<&'a ContractMethod> {
+        let contract = contract_data.get(&address)?;
+        match method {
+            MethodType::Method(x) => contract.methods.get(x).or(contract.fallback.as_ref()),
+            MethodType::Constructor => Some(&contract.constructor),
+            // Without a receive function plain transfers execute the fallback
+            MethodType::Transfer => contract.receive.as_ref().or(contract.fallback.as_ref()),
+            MethodType::Fallback => contract.fallback.as_ref(),
+        }
     }
 
+    /// Adds the dependencies caused by the storage accesses of a method
+    ///
+    /// Entries of mappings whose key can be computed from the values of the transaction are
+    /// treated as separate locations; the other accesses to a mapping conflict with all its entries.
     fn analyze_method(
         method_data: &ContractMethod,
         contract: &mut ContractStorage,
         transaction: &Arc<Mutex<Transaction>>,
+        frame: &Frame,
     ) {
-       //println!("Analyzing method read accesses");
+        let mut conservative = method_data.is_conservative();
+        let mut read_locations = Vec::new();
+        let mut read_entries = Vec::new();
         for access in &method_data.storage_read {
-            // TODO: Replace resolve
-            let memory_address = access.value().resolve().unwrap();
+            match Self::resolve_access(access, frame) {
+                Some((base, None)) => read_locations.push(base),
+                Some((base, Some(slot))) => read_entries.push((base, slot)),
+                None => conservative = true,
+            }
+        }
+        let mut write_locations = Vec::new();
+        let mut write_entries = Vec::new();
+        // Writes are not allowed in a static context, the call would revert
+        let read_only = frame.read_only;
+        for access in method_data.storage_write.iter().filter(|_| !read_only) {
+            match Self::resolve_access(access, frame) {
+                Some((base, None)) => write_locations.push(base),
+                Some((base, Some(slot))) => write_entries.push((base, slot)),
+                None => conservative = true,
+            }
+        }
+        Self::analyze_locations(
+            contract,
+            transaction,
+            read_locations,
+            write_locations,
+            conservative,
+        );
+        if !conservative {
+            Self::analyze_entries(contract, transaction, read_entries, write_entries);
+        }
+    }
+
+    /// Resolves the location of a storage access, together with the slot of the mapping entry
+    fn resolve_access(access: &DataType, frame: &Frame) -> Option<(U256, Option<U256>)> {
+        let base = access.value().resolve()?;
+        match access {
+            DataType::Mapping(_, entry) => Some((base, entry.evaluate(&frame.context))),
+            _ => Some((base, None)),
+        }
+    }
+
+    /// Adds the dependencies caused by accessing entries of mappings, given as (base, slot)
+    fn analyze_entries(
+        contract: &mut ContractStorage,
+        transaction: &Arc<Mutex<Transaction>>,
+        read_entries: Vec<(U256, U256)>,
+        write_entries: Vec<(U256, U256)>,
+    ) {
+        let id = transaction.lock().unwrap().id;
+        // Accesses to unknown entries of the mapping
+        let mut previous = Vec::new();
+        for (base, _) in &read_entries {
+            previous.extend(contract.storage_write.get(base));
+        }
+        for (base, _) in &write_entries {
+            previous.extend(contract.storage_write.get(base));
+            previous.extend(contract.storage_read.get(base));
+        }
+        let previous: Vec<_> = previous.into_iter().flatten().cloned().collect();
+        for trans in previous {
+            let id1 = trans.lock().unwrap().id;
+            if id1 != id {
+                trans.lock().unwrap().required_by(transaction.clone());
+            }
+        }
+        for (base, _) in &read_entries {
+            let list = contract.mapping_read.entry(*base).or_insert_with(Vec::new);
+            list.push(transaction.clone());
+        }
+        for (base, _) in &write_entries {
+            let list = contract.mapping_write.entry(*base).or_insert_with(Vec::new);
+            list.push(transaction.clone());
+        }
+        Self::analyze_locations(
+            contract,
+            transaction,
+            read_entries.into_iter().map(|(_, slot)| slot).collect(),
+            write_entries.into_iter().map(|(_, slot)| slot).collect(),
+            false,
+        );
+    }
+
+    /// Adds the dependencies caused by accessing the given locations of a storage
+    fn analyze_locations(
+        contract: &mut ContractStorage,
+        transaction: &Arc<Mutex<Transaction>>,
+        read_locations: Vec<U256>,
+        write_locations: Vec<U256>,
+        conservative: bool,
+    ) {
+        if conservative {
+            Self::analyze_unknown_access(contract, transaction);
+            return;
+        }
+        let id = transaction.lock().unwrap().id;
+        // Transactions with unknown accesses might have touched any location
+        for trans in &contract.unknown_access {
+            let id1 = trans.lock().unwrap().id;
+            if id1 != id {
+                trans.lock().unwrap().required_by(transaction.clone());
+            }
+        }
+       //println!("Analyzing method read accesses");
+        for memory_address in read_locations {
             let current = contract.storage_write.get(&memory_address);
-            if let Some(list) = current {
-                // If there are transactions writing to this locations
-                for trans in list {
+            // The location might be a mapping, whose entries were written
+            let entries = contract.mapping_write.get(&memory_address);
+            // If there are transactions writing to this locations
+            for trans in current.into_iter().chain(entries).flatten() {
+                let id1 = trans.lock().unwrap().id;
+                if id1 != id {
                     // Add these transactions as dependencies
                     trans.lock().unwrap().required_by(transaction.clone());
                 }
@@ -177,16 +627,14 @@ impl NetBuilder {
         }
        //println!("Analyzing method write accesses");
         // Resolve dependencies for write access
-        for access in &method_data.storage_write {
-            // TODO: Replace resolve
-            let memory_address = access.value().resolve().unwrap();
+        for memory_address in write_locations {
             // Add dependencies to reading transactions
             let current = contract.storage_write.get(&memory_address);
             if let Some(list) = current {
                 // If there are transactions writing to this locations
                 for trans in list {
                     let id1 = trans.lock().unwrap().id;
-                    if id1 != transaction.lock().unwrap().id {
+                    if id1 != id {
                         /* println!(
                             "Adding dependency: ({})=>({})",
                             id1,
@@ -203,7 +651,7 @@ impl NetBuilder {
                 // If there are transactions writing to this locations
                 for trans in list {
                     let id1 = trans.lock().unwrap().id;
-                    if id1 != transaction.lock().unwrap().id {
+                    if id1 != id {
                         /* println!(
                             "Adding dependency: ({})=>({})",
                             id1,
@@ -216,12 +664,44 @@ impl NetBuilder {
                 }
             }
 
+            // Add dependency to transactions accessing the entries of a mapping
+            let read = contract.mapping_read.get(&memory_address);
+            let written = contract.mapping_write.get(&memory_address);
+            for trans in read.into_iter().chain(written).flatten() {
+                let id1 = trans.lock().unwrap().id;
+                if id1 != id {
+                    trans.lock().unwrap().required_by(transaction.clone());
+                }
+            }
+
             // Add yourself to the reading list
             let map = &mut contract.storage_write;
             let read_location = map.entry(memory_address).or_insert_with(Vec::new);
             read_location.push(transaction.clone())
         }
     }
+    /// Serializes a transaction against every other transaction accessing the same contract
+    ///
+    /// Used for methods whose storage accesses are not fully known.
+    fn analyze_unknown_access(
+        contract: &mut ContractStorage,
+        transaction: &Arc<Mutex<Transaction>>,
+    ) {
+        let id = transaction.lock().unwrap().id;
+        let previous = contract
+            .storage_read
+            .values()
+            .chain(contract.storage_write.values())
+            .flatten()
+            .chain(contract.unknown_access.iter());
+        for trans in previous {
+            let id1 = trans.lock().unwrap().id;
+            if id1 != id {
+                trans.lock().unwrap().required_by(transaction.clone());
+            }
+        }
+        contract.unknown_access.push(transaction.clone());
+    }
     pub fn finalize(mut self) -> Vec<Arc<Mutex<Transaction>>> {
         for (c, m, trans) in &mut self.runtime_dependent {
             trans.lock().unwrap().runtime = Some(RuntimeDelegationState {
@@ -1,14 +1,23 @@
-use super::netbuilder::NetBuilder;
+use super::net_executor::execute_net_with_pool;
+use super::netbuilder::{ContractStorage, NetBuilder, NetBuilderError, UnknownCodePolicy};
+use super::runtime_delegation::RuntimeDelegationState;
 use super::transaction::RunningFunction;
-use super::transaction::{MethodType, TransactionDataProvider};
+use super::transaction::{ChainStateProvider, MethodType, Transaction, TransactionDataProvider};
 use crate::contract_data::{ContractData, ContractMethod};
 use crate::contract_utils::DataType;
-use crate::evm_types::StackValue;
+use crate::evm_types::{CallKind, ContractCreation, StackValue};
+use crate::function_analyzer::AnalyzerConfig;
 use ethereum_types::U256;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::thread;
 struct MockTransaction {
     target: U256,
     method: MethodType,
+    sender: Option<U256>,
+    nonce: Option<U256>,
+    value: Option<U256>,
+    calldata: Option<Vec<u8>>,
 }
 impl TransactionDataProvider for MockTransaction {
     fn get_target_contract(&self) -> U256 {
@@ -17,6 +26,18 @@ impl TransactionDataProvider for MockTransaction {
     fn get_target_method(&self) -> MethodType {
         self.method.clone()
     }
+    fn get_sender(&self) -> Option<U256> {
+        self.sender
+    }
+    fn get_nonce(&self) -> Option<U256> {
+        self.nonce
+    }
+    fn get_value(&self) -> Option<U256> {
+        self.value
+    }
+    fn get_calldata(&self) -> Option<Vec<u8>> {
+        self.calldata.clone()
+    }
 }
 
 macro_rules! contract_data {
@@ -41,9 +62,9 @@ macro_rules! contract_data {
                 writeaccess.insert(DataType::Field(StackValue::ActualValue(U256::from($wl))));
             )*
             method.access_write(writeaccess);
-            let mut calls= HashSet::<(StackValue,StackValue)>::new();
+            let mut calls= HashSet::<(StackValue,StackValue,CallKind)>::new();
             $(
-                calls.insert((StackValue::SLoad(Box::from(StackValue::ActualValue(U256::from(0)))),StackValue::ActualValue(U256::from($cl))));
+                calls.insert((StackValue::SLoad(Box::from(StackValue::ActualValue(U256::from(0)))),StackValue::ActualValue(U256::from($cl)),CallKind::Call));
             )*
             method.method_calls(calls);
         )*
@@ -66,8 +87,23 @@ macro_rules! transaction {
         MockTransaction {
             target: U256::from($a),
             method,
+            sender: None,
+            nonce: None,
+            value: Some(U256::zero()),
+            calldata: None,
         }
     }};
+    ($a:expr,$b:expr,from: $s:expr,value: $v:expr) => {{
+        let mut transaction = transaction!($a, $b);
+        transaction.sender = Some(U256::from($s));
+        transaction.value = Some(U256::from($v));
+        transaction
+    }};
+    ($a:expr,$b:expr,from: $s:expr,nonce: $n:expr) => {{
+        let mut transaction = transaction!($a, $b, from: $s, value: 0);
+        transaction.nonce = Some(U256::from($n));
+        transaction
+    }};
 }
 
 #[test]
@@ -101,10 +137,18 @@ fn full_test() {
 
         },
     );
-    builder.new_transaction(&transaction!(10, 0), Box::from(rf.clone()));
-    builder.new_transaction(&transaction!(10, 0x43), Box::from(rf.clone()));
-    builder.new_transaction(&transaction!(10, 0x2347), Box::from(rf.clone()));
-    builder.new_transaction(&transaction!(10, 0x345), Box::from(rf.clone()));
+    builder
+        .new_transaction(&transaction!(10, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x43), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x2347), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x345), Box::from(rf.clone()))
+        .unwrap();
     builder.register_contract(
         u56!(15),
         contract_data! {
@@ -131,8 +175,652 @@ fn full_test() {
 
         },
     );
-    builder.new_transaction(&transaction!(15, 0), Box::from(rf.clone()));
-    builder.new_transaction(&transaction!(15, 0x57), Box::from(rf.clone()));
-    builder.new_transaction(&transaction!(10, 0x345), Box::from(rf.clone()));
-    builder.new_transaction(&transaction!(15, 0x96), Box::from(rf.clone()));
+    builder
+        .new_transaction(&transaction!(15, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(15, 0x57), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x345), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(15, 0x96), Box::from(rf.clone()))
+        .unwrap();
+}
+
+#[test]
+fn unknown_access_test() {
+    let mut builder = NetBuilder::new();
+    let rf = || {};
+    let mut contract = contract_data! {
+         0 => {
+             read: ;
+             write: 0;
+             calls: ;
+         };
+         0x1 =>{
+             read: 0;
+             write: 0;
+             calls: ;
+         };
+         0x2 =>{
+             read: 1;
+             write: ;
+             calls: ;
+         };
+         0x3 =>{
+             read: ;
+             write: ;
+             calls: ;
+         }
+    };
+    contract.get_method(u56!(0x3)).mark_incomplete();
+    builder.register_contract(u56!(10), contract);
+    builder
+        .new_transaction(&transaction!(10, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x1), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x2), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x3), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x2), Box::from(rf.clone()))
+        .unwrap();
+    let start = builder.finalize();
+    assert_eq!(start.len(), 1);
+    let constructor = start[0].lock().unwrap();
+    assert_eq!(constructor.dependencies.len(), 4);
+    for transaction in &constructor.dependencies {
+        let transaction = transaction.lock().unwrap();
+        let count = *transaction.count.lock().unwrap();
+        match transaction.id {
+            // Only the constructor
+            1 | 2 => assert_eq!(count, 1),
+            // Serialized after every previous transaction on the contract
+            3 => assert_eq!(count, 3),
+            // Waits for the transaction with unknown accesses
+            4 => assert_eq!(count, 2),
+            _ => panic!("unexpected transaction"),
+        }
+    }
+}
+
+struct NoState();
+impl ChainStateProvider for NoState {}
+
+#[test]
+fn prune_unknown_access_test() {
+    // 0x1 writes slot 5 and calls a target read from the storage
+    let contract = contract_data! {
+         0x1 =>{
+             read: ;
+             write: 5;
+             calls: 0x2;
+         }
+    };
+    let pruned = Arc::new(Mutex::new(Transaction::new(1, Box::from(|| {}))));
+    let other = Arc::new(Mutex::new(Transaction::new(2, Box::from(|| {}))));
+    let mut storage_write = HashMap::new();
+    storage_write.insert(u56!(5), vec![pruned.clone()]);
+    let storage = ContractStorage {
+        contructor_transition: None,
+        storage_write,
+        storage_read: HashMap::new(),
+        unknown_access: vec![pruned.clone(), other.clone()],
+        creators: Vec::new(),
+        mapping_read: HashMap::new(),
+        mapping_write: HashMap::new(),
+    };
+    let mut contracts = HashMap::new();
+    contracts.insert(u56!(10), storage);
+    let mut contract_data = HashMap::new();
+    contract_data.insert(u56!(10), contract);
+    pruned.lock().unwrap().runtime = Some(RuntimeDelegationState {
+        contract_data,
+        contracts,
+        contracthash: u56!(10),
+        methodhash: u56!(0x1),
+    });
+    {
+        // Held by another worker, e.g. while its dependency counter is decreased
+        let _other = other.lock().unwrap();
+        Transaction::resolve_runtime(&pruned, Box::from(NoState()));
+    }
+    let transaction = pruned.lock().unwrap();
+    let storage = &transaction.runtime.as_ref().unwrap().contracts[&u56!(10)];
+    assert_eq!(storage.unknown_access.len(), 1);
+    assert!(Arc::ptr_eq(&storage.unknown_access[0], &other));
+    assert!(storage.storage_write.is_empty());
+}
+
+#[test]
+fn delegate_and_static_call_test() {
+    let mut builder = NetBuilder::new();
+    let rf = || {};
+    builder.register_contract(
+        u56!(10),
+        contract_data! {
+             0 => {
+                 read: ;
+                 write: ;
+                 calls: ;
+             };
+             0x5 =>{
+                 read: 0;
+                 write: 0;
+                 calls: ;
+             }
+        },
+    );
+    let mut proxy = contract_data! {
+         0 => {
+             read: ;
+             write: ;
+             calls: ;
+         };
+         0x5 =>{
+             read: ;
+             write: ;
+             calls: ;
+         };
+         0x6 =>{
+             read: ;
+             write: ;
+             calls: ;
+         }
+    };
+    // 0x5 forwards its calldata to contract 10 through a DELEGATECALL
+    proxy.get_method(u56!(0x5)).push_external_call((
+        StackValue::ActualValue(u56!(10)),
+        StackValue::CalldataCopy(
+            Box::from(StackValue::CallDataSize),
+            Box::from(StackValue::ActualValue(u56!(0))),
+        ),
+        CallKind::DelegateCall,
+    ));
+    // 0x6 invokes 10::0x5 through a STATICCALL
+    proxy.get_method(u56!(0x6)).push_external_call((
+        StackValue::ActualValue(u56!(10)),
+        StackValue::ActualValue(u56!(0x5)),
+        CallKind::StaticCall,
+    ));
+    builder.register_contract(u56!(20), proxy);
+    builder
+        .new_transaction(&transaction!(10, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(20, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(20, 0x5), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(20, 0x6), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(20, 0x6), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x5), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(20, 0x5), Box::from(rf.clone()))
+        .unwrap();
+    let counts = dependency_counts(builder.finalize());
+    // The delegated write happens on the storage of the proxy
+    assert_eq!(counts[&2], 1);
+    // Static calls only read the storage of contract 10
+    assert_eq!(counts[&3], 2);
+    assert_eq!(counts[&4], 2);
+    // Waits for both readers of slot 0
+    assert_eq!(counts[&5], 3);
+    // Waits for the previous delegated write
+    assert_eq!(counts[&6], 2);
+}
+
+#[test]
+fn contract_creation_test() {
+    let mut builder = NetBuilder::new();
+    let rf = || {};
+    let mut factory = contract_data! {
+         0 => {
+             read: ;
+             write: ;
+             calls: ;
+         };
+         0x1 =>{
+             read: 0;
+             write: 0;
+             calls: ;
+         }
+    };
+    let creation = ContractCreation::Create2(
+        StackValue::CodeSection(vec![0x00]),
+        StackValue::ActualValue(u56!(1)),
+    );
+    let child_address = creation.address(u56!(30)).unwrap();
+    factory
+        .get_method(u56!(0x1))
+        .push_contract_creation(creation);
+    builder.register_contract(u56!(30), factory);
+    let child = contract_data! {
+         0 => {
+             read: ;
+             write: ;
+             calls: ;
+         };
+         0x2 =>{
+             read: 0;
+             write: ;
+             calls: ;
+         }
+    };
+    builder
+        .new_transaction(&transaction!(30, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(30, 0x1), Box::from(rf.clone()))
+        .unwrap();
+    builder.register_contract(child_address, child);
+    builder
+        .new_transaction(&transaction!(child_address, 0x2), Box::from(rf.clone()))
+        .unwrap();
+    let counts = dependency_counts(builder.finalize());
+    // The child contract can only be used after the factory deployed it
+    assert_eq!(counts[&2], 1);
+}
+
+#[test]
+fn balance_test() {
+    let mut builder = NetBuilder::new();
+    let rf = || {};
+    let mut contract = contract_data! {
+         0 => {
+             read: ;
+             write: ;
+             calls: ;
+         };
+         0x1 =>{
+             read: ;
+             write: ;
+             calls: ;
+         };
+         0x2 =>{
+             read: ;
+             write: ;
+             calls: ;
+         }
+    };
+    // 0x1 reads address(this).balance
+    contract
+        .get_method(u56!(0x1))
+        .balance_read
+        .insert(StackValue::Address);
+    builder.register_contract(u56!(10), contract);
+    builder
+        .new_transaction(&transaction!(10, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(
+            &transaction!(10, 0x2, from: 1, value: 5),
+            Box::from(rf.clone()),
+        )
+        .unwrap();
+    builder
+        .new_transaction(
+            &transaction!(10, 0x2, from: 2, value: 0),
+            Box::from(rf.clone()),
+        )
+        .unwrap();
+    builder
+        .new_transaction(
+            &transaction!(10, 0x1, from: 3, value: 0),
+            Box::from(rf.clone()),
+        )
+        .unwrap();
+    builder
+        .new_transaction(
+            &transaction!(10, 0x2, from: 1, value: 0),
+            Box::from(rf.clone()),
+        )
+        .unwrap();
+    let mut unknown_value = transaction!(10, 0x2, from: 4, value: 0);
+    unknown_value.value = None;
+    builder
+        .new_transaction(&unknown_value, Box::from(rf.clone()))
+        .unwrap();
+    let counts = dependency_counts(builder.finalize());
+    assert_eq!(counts[&1], 1);
+    assert_eq!(counts[&2], 1);
+    // Reads the balance modified by the first transfer
+    assert_eq!(counts[&3], 2);
+    // Same sender as the first transfer
+    assert_eq!(counts[&4], 2);
+    // Might transfer value, after the transactions using the balance of the contract
+    assert_eq!(counts[&5], 3);
+}
+
+#[test]
+fn sender_nonce_test() {
+    let mut builder = NetBuilder::new();
+    let rf = || {};
+    builder.register_contract(
+        u56!(10),
+        contract_data! {
+             0 => {
+                 read: ;
+                 write: ;
+                 calls: ;
+             };
+             0x1 =>{
+                 read: 0;
+                 write: ;
+                 calls: ;
+             };
+             0x2 =>{
+                 read: 1;
+                 write: ;
+                 calls: ;
+             }
+        },
+    );
+    builder
+        .new_transaction(&transaction!(10, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(
+            &transaction!(10, 0x1, from: 1, nonce: 0),
+            Box::from(rf.clone()),
+        )
+        .unwrap();
+    builder
+        .new_transaction(
+            &transaction!(10, 0x2, from: 2, nonce: 7),
+            Box::from(rf.clone()),
+        )
+        .unwrap();
+    builder
+        .new_transaction(
+            &transaction!(10, 0x2, from: 1, nonce: 1),
+            Box::from(rf.clone()),
+        )
+        .unwrap();
+    // A nonce already used by the sender is rejected without changing the net
+    let replayed = transaction!(10, 0x1, from: 1, nonce: 1);
+    let error = NetBuilderError::NonceOutOfOrder {
+        sender: u56!(1),
+        nonce: u56!(1),
+        previous: u56!(1),
+    };
+    assert_eq!(
+        builder.new_transaction(&replayed, Box::from(rf.clone())),
+        Err(error)
+    );
+    let counts = dependency_counts(builder.finalize());
+    assert_eq!(counts[&1], 1);
+    assert_eq!(counts[&2], 1);
+    // Waits for the previous transaction of the same sender
+    assert_eq!(counts[&3], 2);
+}
+
+#[test]
+fn unknown_code_test() {
+    let mut builder = NetBuilder::new();
+    let rf = || {};
+    builder.register_contract(
+        u56!(10),
+        contract_data! {
+             0 => {
+                 read: ;
+                 write: ;
+                 calls: ;
+             };
+             0x1 =>{
+                 read: 0;
+                 write: ;
+                 calls: ;
+             }
+        },
+    );
+    builder
+        .new_transaction(&transaction!(10, 0), Box::from(rf.clone()))
+        .unwrap();
+    // Transfers between externally owned accounts
+    let mut transfer = transaction!(20, 0, from: 1, value: 5);
+    transfer.method = MethodType::Transfer;
+    builder
+        .new_transaction(&transfer, Box::from(rf.clone()))
+        .unwrap();
+    let mut transfer = transaction!(21, 0, from: 2, value: 5);
+    transfer.method = MethodType::Transfer;
+    builder
+        .new_transaction(&transfer, Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x1), Box::from(rf.clone()))
+        .unwrap();
+    // Unknown selectors fall back to serializing on the contract
+    builder
+        .new_transaction(&transaction!(10, 0x99), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x1), Box::from(rf.clone()))
+        .unwrap();
+    let counts = dependency_counts(builder.finalize());
+    assert_eq!(counts[&1], 0);
+    assert_eq!(counts[&2], 0);
+    assert_eq!(counts[&3], 1);
+    assert_eq!(counts[&4], 2);
+    assert_eq!(counts[&5], 2);
+
+    let mut builder = NetBuilder::new();
+    builder.unknown_method = UnknownCodePolicy::Ignore;
+    builder.register_contract(
+        u56!(10),
+        contract_data! {
+             0 => {
+                 read: ;
+                 write: ;
+                 calls: ;
+             };
+             0x1 =>{
+                 read: ;
+                 write: 0;
+                 calls: ;
+             }
+        },
+    );
+    builder
+        .new_transaction(&transaction!(10, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x99), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x1), Box::from(rf.clone()))
+        .unwrap();
+    let counts = dependency_counts(builder.finalize());
+    assert_eq!(counts[&1], 1);
+    assert_eq!(counts[&2], 1);
+}
+
+#[test]
+fn mapping_entries_test() {
+    let mut builder = NetBuilder::new();
+    let rf = || {};
+    let mut contract = contract_data! {
+         0 => {
+             read: ;
+             write: ;
+             calls: ;
+         };
+         0x1 =>{
+             read: ;
+             write: ;
+             calls: ;
+         };
+         0x2 =>{
+             read: ;
+             write: ;
+             calls: ;
+         }
+    };
+    // balances[key], with balances at slot 0
+    let entry = |key: StackValue| {
+        let words = vec![(0, key), (32, StackValue::ActualValue(u56!(0)))];
+        let hash = StackValue::Sha3(u56!(0), u56!(64), words);
+        DataType::Mapping(StackValue::ActualValue(u56!(0)), hash)
+    };
+    let mask = StackValue::ActualValue((u56!(1) << 160) - 1);
+    let recipient = StackValue::And(
+        Box::from(mask),
+        Box::from(StackValue::CallDataLoad(Box::from(
+            StackValue::ActualValue(u56!(4)),
+        ))),
+    );
+    // 0x1 is transfer(to, amount): moves tokens from the sender to `to`
+    let accesses: HashSet<DataType> = vec![entry(StackValue::Caller), entry(recipient)]
+        .into_iter()
+        .collect();
+    let transfer = contract.get_method(u56!(0x1));
+    transfer.access_read(accesses.clone());
+    transfer.access_write(accesses);
+    // 0x2 reads the balance of an account stored in slot 5
+    let slot = StackValue::SLoad(Box::from(StackValue::ActualValue(u56!(5))));
+    let unknown = entry(slot);
+    let read = contract.get_method(u56!(0x2));
+    read.access_read(vec![unknown].into_iter().collect());
+    builder.register_contract(u56!(10), contract);
+
+    let transfer = |from: u64, to: u64| {
+        let mut transaction = transaction!(10, 0x1, from: from, value: 0);
+        let mut calldata = vec![0u8; 68];
+        calldata[3] = 0x1;
+        U256::from(to).to_big_endian(&mut calldata[4..36]);
+        transaction.calldata = Some(calldata);
+        transaction
+    };
+    builder
+        .new_transaction(&transaction!(10, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transfer(1, 2), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transfer(3, 4), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transfer(2, 5), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transaction!(10, 0x2), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&transfer(6, 7), Box::from(rf.clone()))
+        .unwrap();
+    // Without calldata the recipient is not known
+    builder
+        .new_transaction(
+            &transaction!(10, 0x1, from: 8, value: 0),
+            Box::from(rf.clone()),
+        )
+        .unwrap();
+    let counts = dependency_counts(builder.finalize());
+    assert_eq!(counts[&1], 1);
+    // Disjoint accounts
+    assert_eq!(counts[&2], 1);
+    // Spends the tokens received with the first transfer
+    assert_eq!(counts[&3], 2);
+    // Might read any balance
+    assert_eq!(counts[&4], 4);
+    // Writes balances that might have been read
+    assert_eq!(counts[&5], 2);
+    // Might write any balance
+    assert_eq!(counts[&6], 6);
+}
+
+#[test]
+fn ballot_test() {
+    // The Ballot example of the Solidity documentation, compiled by solc 0.6.6
+    let mut builder = NetBuilder::new();
+    let rf = || {};
+    let code = include_bytes!("../../contracts/bytecodes/ballot");
+    builder.register_code(u56!(10), code).unwrap();
+    let call = |selector: u32, from: u64, account: u64| {
+        let mut transaction = transaction!(10, selector, from: from, value: 0);
+        let mut calldata = vec![0u8; 36];
+        calldata[..4].copy_from_slice(&selector.to_be_bytes());
+        U256::from(account).to_big_endian(&mut calldata[4..36]);
+        transaction.calldata = Some(calldata);
+        transaction
+    };
+    // giveRightToVote(voter) writes voters[voter]; only the chairperson can call it, but the
+    // senders are different so that they do not order the transactions
+    let give_right_to_vote = |from: u64, voter: u64| call(0x9e7b8d61, from, voter);
+    // delegate(to) follows voters[to].delegate in a loop, then writes the voter reached
+    let delegate = |from: u64, to: u64| call(0x5c19a95c, from, to);
+    builder
+        .new_transaction(&transaction!(10, 0), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&give_right_to_vote(1, 0xa), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&give_right_to_vote(2, 0xb), Box::from(rf.clone()))
+        .unwrap();
+    builder
+        .new_transaction(&delegate(0xc, 0xd), Box::from(rf.clone()))
+        .unwrap();
+    let counts = dependency_counts(builder.finalize());
+    // Disjoint voters
+    assert_eq!(counts[&1], 1);
+    assert_eq!(counts[&2], 1);
+    // The voter reached by the loop is only known at runtime, it might be any of them
+    assert_eq!(counts[&3], 3);
+}
+
+/// Collects the dependency counter of every transaction reachable from the starting ones
+fn dependency_counts(start: Vec<Arc<Mutex<Transaction>>>) -> HashMap<usize, usize> {
+    let mut counts = HashMap::new();
+    let mut to_visit = start;
+    while let Some(transaction) = to_visit.pop() {
+        let transaction = transaction.lock().unwrap();
+        counts.insert(transaction.id, *transaction.count.lock().unwrap());
+        to_visit.extend(transaction.dependencies.iter().cloned());
+    }
+    counts
+}
+
+#[test]
+fn shared_pool_test() {
+    let mut config = AnalyzerConfig::new(2);
+    config.single_thread_below = 0;
+    let pool = config.pool();
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+    for _ in 0..2 {
+        let mut builder = NetBuilder::new();
+        builder.analyzer = Some(config.clone());
+        let code = include_bytes!("../../contracts/bytecodes/storage");
+        builder.register_code(u56!(10), code).unwrap();
+        for sender in 0..4 {
+            let threads = threads.clone();
+            let rf = move || {
+                threads.lock().unwrap().insert(thread::current().id());
+            };
+            let transaction = transaction!(10, 0x6057361d, from: sender, value: 0);
+            builder
+                .new_transaction(&transaction, Box::from(rf))
+                .unwrap();
+        }
+        execute_net_with_pool(builder.finalize(), 4, &pool);
+    }
+    // Both nets ran on the threads of the pool that analyzed the contracts
+    assert!(!threads.lock().unwrap().is_empty());
+    assert!(threads.lock().unwrap().len() <= 2);
 }
@@ -1,6 +1,7 @@
 use super::netbuilder::ContractStorage;
 use super::runtime_delegation::RuntimeDelegationState;
 use crate::contract_data::{ContractData, ContractMethod};
+use crate::evm_types::ConcreteContext;
 use ethereum_types::U256;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -20,11 +21,58 @@ pub struct Transaction {
 pub trait TransactionDataProvider {
     fn get_target_contract(&self) -> U256;
     fn get_target_method(&self) -> MethodType;
+    /// The account sending the transaction, if known
+    fn get_sender(&self) -> Option<U256> {
+        None
+    }
+    /// The nonce of the sender, if known
+    fn get_nonce(&self) -> Option<U256> {
+        None
+    }
+    /// The amount of wei sent with the transaction, if known
+    ///
+    /// When `None` the transaction is assumed to possibly transfer value to the target.
+    fn get_value(&self) -> Option<U256> {
+        None
+    }
+    /// The complete calldata of the transaction, selector included, if known
+    fn get_calldata(&self) -> Option<Vec<u8>> {
+        None
+    }
+    /// The number of the block including the transaction, if known
+    fn get_number(&self) -> Option<U256> {
+        None
+    }
+    /// The timestamp of the block including the transaction, if known
+    fn get_timestamp(&self) -> Option<U256> {
+        None
+    }
+    /// The beneficiary of the block including the transaction, if known
+    fn get_coinbase(&self) -> Option<U256> {
+        None
+    }
+    /// The context the target method is executed in, used to evaluate its storage accesses
+    fn get_context(&self) -> ConcreteContext {
+        ConcreteContext {
+            address: Some(self.get_target_contract()),
+            caller: self.get_sender(),
+            origin: self.get_sender(),
+            value: self.get_value(),
+            calldata: self.get_calldata(),
+            number: self.get_number(),
+            timestamp: self.get_timestamp(),
+            coinbase: self.get_coinbase(),
+        }
+    }
 }
 #[derive(Clone,Debug)]
 pub enum MethodType {
     Constructor,
     Method(U256),
+    /// A plain value transfer, sent without calldata
+    Transfer,
+    /// A call whose calldata does not start with a known selector
+    Fallback,
 }
 
 impl Transaction {
@@ -75,12 +123,16 @@ impl Transaction {
         }
         rec
     }
+    /// Prunes the accesses predicted for the method of `transaction` and the methods it calls,
+    /// returning the transactions left without dependencies
     pub fn resolve_runtime(
-        &mut self,
+        transaction: &Arc<Mutex<Transaction>>,
         _state_provider: Box<dyn ChainStateProvider>,
     ) -> Vec<Arc<Mutex<Transaction>>> {
+        let mut guard = transaction.lock().unwrap();
+        let this = &mut *guard;
         let mut freed = Vec::new();
-        if let Some(rs) = &mut self.runtime {
+        if let Some(rs) = &mut this.runtime {
             let contract = rs.contracthash;
             let method = rs.methodhash;
             let mut methods_to_prune = Vec::new();
@@ -96,20 +148,17 @@ impl Transaction {
             ));
             while !methods_to_prune.is_empty() {
                 let (contract, method) = methods_to_prune.pop().unwrap();
-                prune_method(&mut rs.contracts.get_mut(&contract).unwrap(), method);
+                if let Some(storage) = rs.contracts.get_mut(&contract) {
+                    prune_method(storage, method, transaction);
+                }
                 let mut holder = Vec::new();
                 for call in &method.method_call {
-                    let contract = call.0.resolve().unwrap();
-                    let method = call.1.resolve().unwrap();
-                    holder.push((
-                        contract,
-                        rs.contract_data
-                            .get(&contract)
-                            .unwrap()
-                            .methods
-                            .get(&method)
-                            .unwrap(),
-                    ));
+                    // The accesses of calls to unknown targets are kept
+                    if let (Some(contract), Some(method)) = (call.0.resolve(), call.1.resolve()) {
+                        if let Some(data) = rs.contract_data.get(&contract) {
+                            holder.extend(data.methods.get(&method).map(|m| (contract, m)));
+                        }
+                    }
                 }
                 methods_to_prune.extend(holder);
             }
@@ -118,19 +167,19 @@ impl Transaction {
                 //Read
                 for (_, list) in &storage.storage_read {
                     for transaction in list {
-                        if transaction.lock().unwrap().id > self.id {
+                        if transaction.lock().unwrap().id > this.id {
                             let tr_lock = transaction.lock().unwrap();
                             let mut count = tr_lock.count.lock().unwrap();
                             *count -= 1;
                             if *count == 0 {
                                 freed.push(transaction.clone())
                             }
-                            if let Some(i) = self
+                            if let Some(i) = this
                                 .dependencies
                                 .iter()
                                 .position(|t| Arc::ptr_eq(t, transaction))
                             {
-                                self.dependencies.remove(i);
+                                this.dependencies.remove(i);
                             }
                         }
                     }
@@ -143,20 +192,29 @@ impl Transaction {
         (self.runner)()
     }
 }
-fn prune_method(storage: &mut ContractStorage, method: &ContractMethod) {
+fn prune_method(
+    storage: &mut ContractStorage,
+    method: &ContractMethod,
+    pruned: &Arc<Mutex<Transaction>>,
+) {
+    storage
+        .unknown_access
+        .retain(|transaction| !Arc::ptr_eq(transaction, pruned));
     for access in &method.storage_read {
         // TODO: Replace resolve
-        let memory_address = access.value().resolve().unwrap();
-        storage.storage_write.remove(&memory_address);
+        if let Some(memory_address) = access.value().resolve() {
+            storage.storage_write.remove(&memory_address);
+        }
     }
 
     // Resolve dependencies for write access
     for access in &method.storage_write {
         // TODO: Replace resolve
-        let memory_address = access.value().resolve().unwrap();
-        // Add dependencies to reading transactions
-        storage.storage_read.remove(&memory_address);
-        // Add dependency to writing transactions
-        storage.storage_read.remove(&memory_address);
+        if let Some(memory_address) = access.value().resolve() {
+            // Remove dependencies to reading transactions
+            storage.storage_read.remove(&memory_address);
+            // Remove dependency to writing transactions
+            storage.storage_write.remove(&memory_address);
+        }
     }
 }